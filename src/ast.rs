@@ -0,0 +1,156 @@
+use crate::field::DirectionEnum;
+use crate::lang::LanguageEnum;
+use crate::parser::{Span, SyntaxEnum};
+
+/// Разобранный скрипт: основная программа, процедуры и имена переменных
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub main: Block,
+    pub procs: Vec<Proc>,
+    /// Имена переменных, номер в векторе - номер регистра
+    pub registers: Vec<String>,
+    /// На каком языке и в каком синтаксисе был написан скрипт
+    pub language: LanguageEnum,
+    pub syntax: SyntaxEnum,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Proc {
+    pub name: String,
+    pub span: Span,
+    pub body: Block,
+}
+
+/// Команды одного уровня вложенности
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Block {
+    pub statements: Vec<Statement>,
+    /// Слово, которым блок закрыт: endIf, else, `}` и т.п.
+    /// У основной программы и у незакрытого блока его нет
+    pub end: Option<Span>,
+}
+
+/// Команда скрипта и место, откуда она взялась
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub node: NodeEnum,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeEnum {
+    Action(ActionEnum),
+    If(If),
+    Loop(Loop),
+    Repeat(Repeat),
+    Call(String),
+    /// Остановить бота насовсем
+    Stop,
+    Set(usize, OperandEnum),
+    Add(usize, OperandEnum),
+    Sub(usize, OperandEnum),
+    /// То же, что `Add` с 1, но в скрипте было написано inc
+    Inc(usize),
+    /// То же, что `Sub` с 1, но в скрипте было написано dec
+    Dec(usize),
+    /// Сказать число ботам поблизости
+    Say(OperandEnum),
+    /// Забрать в переменную самое старое из услышанных чисел
+    Listen(usize),
+}
+
+/// То, на что бот тратит ход
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ActionEnum {
+    Step,
+    TurnLeft,
+    TurnRight,
+    TurnRandom,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct If {
+    pub condition: ConditionEnum,
+    pub then_block: Block,
+    pub else_block: Option<Block>,
+}
+
+/// Цикл с предусловием
+#[derive(Debug, Clone, PartialEq)]
+pub struct Loop {
+    pub condition: ConditionEnum,
+    pub body: Block,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Repeat {
+    pub count: OperandEnum,
+    pub body: Block,
+}
+
+/// Аргумент команды: число или номер переменной
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OperandEnum {
+    Value(i32),
+    Register(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompareEnum {
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+}
+
+impl CompareEnum {
+    pub fn check(self, left: i32, right: i32) -> bool {
+        match self {
+            CompareEnum::Less => left < right,
+            CompareEnum::LessOrEqual => left <= right,
+            CompareEnum::Greater => left > right,
+            CompareEnum::GreaterOrEqual => left >= right,
+            CompareEnum::Equal => left == right,
+            CompareEnum::NotEqual => left != right,
+        }
+    }
+}
+
+/// Условие для if/loop: датчики и сравнения переменных, связанные через not/and/or
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConditionEnum {
+    Sensor(SensorEnum),
+    Compare(OperandEnum, CompareEnum, OperandEnum),
+    /// Среди услышанных и еще не прочитанных есть это число
+    Heard(OperandEnum),
+    Not(Box<ConditionEnum>),
+    And(Box<ConditionEnum>, Box<ConditionEnum>),
+    Or(Box<ConditionEnum>, Box<ConditionEnum>),
+}
+
+impl ConditionEnum {
+    /// Сколько уровней not/and/or над самым глубоким датчиком или сравнением
+    pub fn depth(&self) -> usize {
+        match self {
+            ConditionEnum::Sensor(_) | ConditionEnum::Compare(..) | ConditionEnum::Heard(_) => 0,
+            ConditionEnum::Not(inner) => inner.depth() + 1,
+            ConditionEnum::And(left, right) | ConditionEnum::Or(left, right) => {
+                left.depth().max(right.depth()) + 1
+            }
+        }
+    }
+}
+
+/// Что бот может проверить по показаниям своих датчиков
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SensorEnum {
+    Free,
+    FreeBehind,
+    WallLeft,
+    WallRight,
+    BotAhead,
+    AtEdge,
+    Facing(DirectionEnum),
+}
@@ -0,0 +1,246 @@
+//! Компилятор скриптов без браузера: проверка, листинг команд и байткод.
+//! Ошибка в скрипте - код выхода 1, кривые аргументы - 2.
+
+use bots::bot::Bot;
+use bots::bytecode;
+use bots::disasm;
+use bots::lang::{self, ArgsErrorEnum, LanguageEnum};
+use bots::parser::SyntaxEnum;
+use std::process;
+
+const USAGE: &str = "\
+Использование:
+  botc check [опции] скрипт...   проверить скрипты, ошибки в виде файл:строка:колонка: текст
+  botc disasm [опции] скрипт     листинг команд бота с адресами и строками скрипта
+  botc build [опции] скрипт      байткод в base64, его можно загрузить вместо скрипта
+Опции:
+  --lang en|ru             язык ошибок, по умолчанию ru
+  --syntax keywords|braces синтаксис блоков, по умолчанию по расширению файла (.bbot - со скобками)
+  --no-optimize            не оптимизировать программу (для disasm и build)
+  -o файл                  куда записать байткод, по умолчанию в stdout (для build)";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CommandKindEnum {
+    Check,
+    Disasm,
+    Build,
+}
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    command: CommandKindEnum,
+    language: LanguageEnum,
+    syntax: Option<SyntaxEnum>,
+    optimize: bool,
+    output: Option<String>,
+    files: Vec<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, ArgsErrorEnum> {
+    let mut args = args.iter();
+    let command = match args.next().map(String::as_str) {
+        Some("check") => CommandKindEnum::Check,
+        Some("disasm") => CommandKindEnum::Disasm,
+        Some("build") => CommandKindEnum::Build,
+        Some(other) => return Err(ArgsErrorEnum::UnknownCommand(other.to_string())),
+        None => return Err(ArgsErrorEnum::NoCommand),
+    };
+    let mut options = Options {
+        command,
+        language: LanguageEnum::Russian,
+        syntax: None,
+        optimize: true,
+        output: None,
+        files: vec![],
+    };
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| ArgsErrorEnum::MissingValue(arg.clone()))
+        };
+        match arg.as_str() {
+            "--lang" => {
+                let code = value()?;
+                options.language = LanguageEnum::from_code(code)
+                    .ok_or_else(|| ArgsErrorEnum::UnknownLanguage(code.clone()))?;
+            }
+            "--syntax" => {
+                let code = value()?;
+                options.syntax = Some(
+                    SyntaxEnum::from_code(code)
+                        .ok_or_else(|| ArgsErrorEnum::UnknownSyntax(code.clone()))?,
+                );
+            }
+            "--no-optimize" => options.optimize = false,
+            "-o" => options.output = Some(value()?.clone()),
+            flag if flag.starts_with('-') => {
+                return Err(ArgsErrorEnum::UnknownOption(flag.to_string()))
+            }
+            file => options.files.push(file.to_string()),
+        }
+    }
+    match (options.command, options.files.len()) {
+        (_, 0) => Err(ArgsErrorEnum::NoScripts),
+        (CommandKindEnum::Check, _) | (_, 1) => Ok(options),
+        _ => Err(ArgsErrorEnum::OneScriptOnly),
+    }
+}
+
+/// Бот из файла: скрипт компилируется, байткод в base64 загружается как есть.
+/// Ошибки - готовые строки для вывода, с именем файла и местом в скрипте
+fn load_bot(file: &str, options: &Options) -> Result<Bot, Vec<String>> {
+    let data = std::fs::read_to_string(file).map_err(|err| vec![format!("{}: {}", file, err)])?;
+    let mut bot = Bot::new();
+    if bytecode::is_base64(&data) {
+        bytecode::from_base64(&data)
+            .and_then(|bytes| bot.load_from_bytecode(&bytes))
+            .map_err(|err| {
+                vec![format!(
+                    "{}: {}",
+                    file,
+                    lang::bytecode_error(&err, options.language)
+                )]
+            })?;
+        return Ok(bot);
+    }
+    bot.set_optimize(options.optimize);
+    bot.set_syntax(
+        options
+            .syntax
+            .unwrap_or_else(|| SyntaxEnum::from_file_name(file)),
+    );
+    bot.load_from_string(data).map_err(|errors| {
+        errors
+            .iter()
+            .map(|err| {
+                format!(
+                    "{}:{}:{}: {}",
+                    file,
+                    err.line(),
+                    err.column(),
+                    lang::compile_error_text(err, options.language)
+                )
+            })
+            .collect::<Vec<String>>()
+    })?;
+    Ok(bot)
+}
+
+fn run(options: &Options) -> Result<(), Vec<String>> {
+    match options.command {
+        CommandKindEnum::Check => {
+            //проверяются все файлы, даже если в первом уже нашлись ошибки
+            let errors: Vec<String> = options
+                .files
+                .iter()
+                .filter_map(|file| load_bot(file, options).err())
+                .flatten()
+                .collect();
+            if errors.is_empty() {
+                Ok(())
+            } else {
+                Err(errors)
+            }
+        }
+        CommandKindEnum::Disasm => {
+            let bot = load_bot(&options.files[0], options)?;
+            print!("{}", disasm::listing(&bot.disassemble()));
+            Ok(())
+        }
+        CommandKindEnum::Build => {
+            let bot = load_bot(&options.files[0], options)?;
+            let bytes = bytecode::encode(&bot.to_module()).map_err(|err| {
+                vec![format!(
+                    "{}: {}",
+                    options.files[0],
+                    lang::bytecode_error(&err, options.language)
+                )]
+            })?;
+            let encoded = bytecode::to_base64(&bytes);
+            match &options.output {
+                Some(output) => std::fs::write(output, encoded + "\n")
+                    .map_err(|err| vec![format!("{}: {}", output, err)]),
+                None => {
+                    println!("{}", encoded);
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return;
+    }
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(err) => {
+            let language = lang::language_from_args(&args);
+            eprintln!("{}\n\n{}", lang::args_error(&err, language), USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(errors) = run(&options) {
+        for error in errors {
+            eprintln!("{}", error);
+        }
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn options_and_diagnostics() {
+        let options = parse_args(&args("build --no-optimize -o a.txt a.bot")).unwrap();
+        assert_eq!(options.command, CommandKindEnum::Build);
+        assert!(!options.optimize);
+        assert_eq!(options.output.as_deref(), Some("a.txt"));
+        assert!(parse_args(&args("check a.bot b.bbot")).is_ok());
+        assert!(parse_args(&args("disasm a.bot b.bot")).is_err());
+        assert!(parse_args(&args("run a.bot")).is_err());
+        assert!(parse_args(&args("check --syntax curly a.bot")).is_err());
+
+        let dir = std::env::temp_dir().join(format!("botc-test-{}", process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("bad.bot");
+        std::fs::write(&file, "loop\n  jump\nendLoop").unwrap();
+        let file = file.to_str().unwrap().to_string();
+        let options = parse_args(&args(&format!("check --lang en {}", file))).unwrap();
+        assert_eq!(
+            run(&options),
+            Err(vec![format!("{}:2:3: Unknown command: jump", file)])
+        );
+
+        let file = dir.join("good.bbot");
+        std::fs::write(&file, "while free { step }").unwrap();
+        let file = file.to_str().unwrap();
+        let bot = load_bot(
+            file,
+            &parse_args(&args(&format!("check {}", file))).unwrap(),
+        )
+        .unwrap();
+        let built = dir.join("good.txt");
+        let built = built.to_str().unwrap();
+        let options = parse_args(&args(&format!("build {} -o {}", file, built))).unwrap();
+        assert_eq!(run(&options), Ok(()));
+        //в байткоде нет строк скрипта, сравниваются только команды
+        let texts = |bot: &Bot| -> Vec<String> {
+            bot.disassemble()
+                .into_iter()
+                .map(|instruction| instruction.text)
+                .collect()
+        };
+        assert_eq!(texts(&load_bot(built, &options).unwrap()), texts(&bot));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,247 @@
+//! Поле с ботами без браузера: создает поле, загружает скрипты, делает N ходов
+//! и печатает поле в ASCII. Ошибка в скрипте или нет места для бота - код выхода 1,
+//! кривые аргументы - 2.
+
+use bots::bot::ProgramEndEnum;
+use bots::bytecode;
+use bots::field::{AddBotError, Field, DEFAULT_SAY_RANGE};
+use bots::lang::{self, ArgsErrorEnum, LanguageEnum};
+use bots::parser::SyntaxEnum;
+use bots::render::{self, Style};
+use std::process;
+
+const USAGE: &str = "\
+Использование: bots-sim [опции] скрипт...
+  --width N              ширина поля, по умолчанию 10, не больше 1000
+  --height N             высота поля, по умолчанию 10, не больше 1000
+  --walls P              процент стен, по умолчанию 0
+  --range N              как далеко слышно say, по умолчанию 3
+  --seed S               сид поля, по умолчанию случайный
+  --ticks N              сколько ходов сделать, по умолчанию 100
+  --frames               печатать поле после каждого хода, а не только в конце
+  --halt                 бот останавливается в конце программы, а не начинает ее сначала
+  --lang en|ru           язык ошибок, по умолчанию ru
+  --style ascii|unicode  чем рисовать поле, по умолчанию ascii
+Скрипт с расширением .bbot - со скобками, файл с байткодом в base64 загружается без компиляции";
+
+/// Больше поле в терминале все равно не разглядеть
+const MAX_SIDE: i32 = 1000;
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    width: i32,
+    height: i32,
+    wall_percent: i32,
+    say_range: i32,
+    seed: Option<u64>,
+    ticks: usize,
+    frames: bool,
+    style: Style,
+    halt: bool,
+    language: LanguageEnum,
+    files: Vec<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, ArgsErrorEnum> {
+    let mut options = Options {
+        width: 10,
+        height: 10,
+        wall_percent: 0,
+        say_range: DEFAULT_SAY_RANGE,
+        seed: None,
+        ticks: 100,
+        frames: false,
+        style: render::ASCII,
+        halt: false,
+        language: LanguageEnum::Russian,
+        files: vec![],
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| ArgsErrorEnum::MissingValue(arg.clone()))
+        };
+        match arg.as_str() {
+            "--width" => options.width = parse_number(arg, value()?)?,
+            "--height" => options.height = parse_number(arg, value()?)?,
+            "--walls" => options.wall_percent = parse_number(arg, value()?)?,
+            "--range" => options.say_range = parse_number(arg, value()?)?,
+            "--seed" => options.seed = Some(parse_number(arg, value()?)?),
+            "--ticks" => options.ticks = parse_number(arg, value()?)?,
+            "--frames" => options.frames = true,
+            "--halt" => options.halt = true,
+            "--lang" => {
+                let code = value()?;
+                options.language = LanguageEnum::from_code(code)
+                    .ok_or_else(|| ArgsErrorEnum::UnknownLanguage(code.clone()))?;
+            }
+            "--style" => {
+                let code = value()?;
+                options.style = Style::from_code(code)
+                    .ok_or_else(|| ArgsErrorEnum::UnknownStyle(code.clone()))?;
+            }
+            flag if flag.starts_with("--") => {
+                return Err(ArgsErrorEnum::UnknownOption(flag.to_string()))
+            }
+            file => options.files.push(file.to_string()),
+        }
+    }
+    if !(1..=MAX_SIDE).contains(&options.width) || !(1..=MAX_SIDE).contains(&options.height) {
+        return Err(ArgsErrorEnum::FieldSize { max: MAX_SIDE });
+    }
+    if !(0..100).contains(&options.wall_percent) {
+        return Err(ArgsErrorEnum::WallPercent);
+    }
+    if options.files.is_empty() {
+        return Err(ArgsErrorEnum::NoScripts);
+    }
+    Ok(options)
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, ArgsErrorEnum> {
+    value.trim().parse().map_err(|_| ArgsErrorEnum::NotANumber {
+        flag: flag.to_string(),
+        value: value.to_string(),
+    })
+}
+
+/// Загружает скрипты в поле. Ошибки всех файлов сразу, чтобы их можно было поправить за один раз
+fn load_bots(field: &mut Field, options: &Options) -> Result<(), Vec<String>> {
+    let mut errors = vec![];
+    for file in &options.files {
+        let data = match std::fs::read_to_string(file) {
+            Ok(data) => data,
+            Err(err) => {
+                errors.push(format!("{}: {}", file, err));
+                continue;
+            }
+        };
+        let added = if bytecode::is_base64(&data) {
+            bytecode::from_base64(&data)
+                .map_err(AddBotError::Bytecode)
+                .and_then(|bytes| field.add_compiled_bot(&bytes))
+        } else {
+            field.add_bot_with_syntax(data, SyntaxEnum::from_file_name(file))
+        };
+        match added {
+            Ok(()) => {}
+            //у каждой ошибки компиляции свое место в скрипте, так что по строке на ошибку
+            Err(AddBotError::Compile(compile_errors)) => errors.extend(
+                compile_errors
+                    .iter()
+                    .map(|err| format!("{}: {}", file, lang::compile_error(err, options.language))),
+            ),
+            Err(err) => errors.push(format!(
+                "{}: {}",
+                file,
+                lang::add_bot_error(&err, options.language)
+            )),
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+fn run(options: &Options) -> i32 {
+    let mut field = options.seed.map_or_else(Field::new, Field::with_seed);
+    field.width = options.width;
+    field.height = options.height;
+    field.say_range = options.say_range;
+    if options.halt {
+        field.program_end = ProgramEndEnum::Halt;
+    }
+    field.add_random_wall(options.wall_percent);
+    if let Err(errors) = load_bots(&mut field, options) {
+        for error in errors {
+            eprintln!("{}", error);
+        }
+        return 1;
+    }
+    println!("Сид: {}", field.seed());
+    let mut tick = 0;
+    while tick < options.ticks && !field.all_stopped() {
+        tick += 1;
+        let result = field.step();
+        if options.frames {
+            println!("Ход {}\n{}", tick, render::render(&field, options.style));
+        }
+        if let Err(err) = result {
+            if !options.frames {
+                print!("{}", render::render(&field, options.style));
+            }
+            eprintln!("{}", lang::tick_error(tick, &err, options.language));
+            return 1;
+        }
+    }
+    if !options.frames {
+        println!("Ход {}\n{}", tick, render::render(&field, options.style));
+    }
+    0
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", USAGE);
+        return;
+    }
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(err) => {
+            let language = lang::language_from_args(&args);
+            eprintln!("{}\n\n{}", lang::args_error(&err, language), USAGE);
+            process::exit(2);
+        }
+    };
+    process::exit(run(&options));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn options() {
+        let options = parse_args(&args("--width 4 --seed 7 --frames a.bot b.bbot")).unwrap();
+        assert_eq!(options.width, 4);
+        assert_eq!(options.seed, Some(7));
+        assert_eq!(options.say_range, DEFAULT_SAY_RANGE);
+        assert!(options.frames);
+        assert_eq!(options.files, vec!["a.bot", "b.bbot"]);
+        assert!(parse_args(&args("--width x a.bot")).is_err());
+        assert!(parse_args(&args("--ticks 5")).is_err());
+        assert!(parse_args(&args("--walls 100 a.bot")).is_err());
+        assert!(parse_args(&args("--width 1001 a.bot")).is_err());
+        assert!(parse_args(&args("--height 0 a.bot")).is_err());
+
+        let options = parse_args(&args("--style unicode a.bot")).unwrap();
+        assert_eq!(options.style, render::UNICODE);
+        assert!(parse_args(&args("--style emoji a.bot")).is_err());
+    }
+
+    #[test]
+    fn full_field_is_an_error() {
+        let dir = std::env::temp_dir().join(format!("bots-sim-test-{}", process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("a.bot");
+        std::fs::write(&file, "step").unwrap();
+        let file = file.to_str().unwrap();
+        let mut field = Field::with_seed(1);
+        field.width = 1;
+        field.height = 1;
+        let options = parse_args(&args(&format!("--lang en {} {}", file, file))).unwrap();
+        assert_eq!(
+            load_bots(&mut field, &options),
+            Err(vec![format!("{}: No free cells left on the field", file)])
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,31 +1,145 @@
-use rand::Rng;
+use crate::ast::{ConditionEnum, OperandEnum, SensorEnum};
+use crate::bytecode::{self, BytecodeError, Module};
+use crate::codegen;
+use crate::decompiler;
+use crate::disasm::{self, Instruction};
+use crate::field::DirectionEnum;
+use crate::lang::{self, LanguageEnum};
+use crate::optimizer;
+use crate::parser::{self, CompileError, Span, SyntaxEnum};
+use crate::printer;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashSet;
+use std::fmt;
 
-enum OpCodeEnum {
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandEnum {
     Step,
     TurnLeft,
     TurnRight,
     TurnRandom,
-    LoopStart,
-    LoopEnd,
-    If,
-    EndIf,
+    GoTo(i32),
+    /// Переход, если условие ложно
+    GoToNE(ConditionEnum, i32),
+    /// Переход, если условие истинно
+    GoToIf(ConditionEnum, i32),
+    /// Кладет счетчик повторов на стек
+    PushCounter(OperandEnum),
+    /// Если счетчик на вершине стека кончился - снимает его и переходит,
+    /// иначе уменьшает на единицу
+    CounterNext(i32),
+    /// Вызов процедуры, адрес возврата кладется на стек вызовов
+    Call(i32),
+    Return,
+    /// Бот останавливается насовсем
+    Stop,
+    /// Записать значение в переменную
+    Set(usize, OperandEnum),
+    /// Прибавить к переменной
+    Add(usize, OperandEnum),
+    /// Вычесть из переменной
+    Sub(usize, OperandEnum),
+    /// Сказать число, поле разнесет его соседям после хода
+    Say(OperandEnum),
+    /// Забрать самое старое услышанное число в переменную.
+    /// Если ничего не слышно, переменная не меняется
+    Listen(usize),
 }
 
-#[derive(Debug)]
-enum CommandEnum {
-    Step,
-    TurnLeft,
-    TurnRight,
-    TurnRandom,
-    GoTo(i32),
-    GoToNE(i32),
+impl CommandEnum {
+    /// Адрес, на который команда может перейти
+    pub fn target(&self) -> Option<i32> {
+        match self {
+            CommandEnum::GoTo(ptr)
+            | CommandEnum::GoToNE(_, ptr)
+            | CommandEnum::GoToIf(_, ptr)
+            | CommandEnum::CounterNext(ptr)
+            | CommandEnum::Call(ptr) => Some(*ptr),
+            _ => None,
+        }
+    }
+
+    /// Меняет адрес перехода, у команд без перехода ничего не делает
+    pub fn set_target(&mut self, target: i32) {
+        match self {
+            CommandEnum::GoTo(ptr)
+            | CommandEnum::GoToNE(_, ptr)
+            | CommandEnum::GoToIf(_, ptr)
+            | CommandEnum::CounterNext(ptr)
+            | CommandEnum::Call(ptr) => *ptr = target,
+            _ => {}
+        }
+    }
 }
 
-#[derive(PartialEq)]
-enum ParserStateEnum {
-    Loop,
-    If,
-    Root,
+/// Максимальная глубина вложенных call
+pub const MAX_CALL_DEPTH: usize = 64;
+
+/// Сколько команд бот может выполнить за один ход в `do_tick`
+pub const DEFAULT_TICK_BUDGET: usize = 1000;
+
+/// Сколько разных переменных может быть в одном скрипте
+pub const MAX_REGISTERS: usize = 16;
+
+/// Насколько глубоко могут быть вложены not/and/or в одном условии.
+/// Глубже не пропускает ни парсер, ни загрузчик байткода, чтобы не положить стек
+pub const MAX_CONDITION_DEPTH: usize = 64;
+
+/// Сколько непрочитанных чисел бот может держать, новые сверх этого теряются
+pub const MAX_INBOX: usize = 16;
+
+/// Показания датчиков бота, поле считает их перед каждым ходом.
+/// Стеной считается и стена, и край поля
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sensors {
+    /// Клетка впереди свободна
+    pub free_ahead: bool,
+    /// Клетка сзади свободна
+    pub free_behind: bool,
+    pub wall_left: bool,
+    pub wall_right: bool,
+    /// Впереди стоит другой бот
+    pub bot_ahead: bool,
+    /// Бот смотрит на край поля
+    pub at_edge: bool,
+    pub direction: DirectionEnum,
+}
+
+impl Sensors {
+    fn check_sensor(&self, sensor: SensorEnum) -> bool {
+        match sensor {
+            SensorEnum::Free => self.free_ahead,
+            SensorEnum::FreeBehind => self.free_behind,
+            SensorEnum::WallLeft => self.wall_left,
+            SensorEnum::WallRight => self.wall_right,
+            SensorEnum::BotAhead => self.bot_ahead,
+            SensorEnum::AtEdge => self.at_edge,
+            SensorEnum::Facing(direction) => self.direction == direction,
+        }
+    }
+}
+
+/// Ошибка, из-за которой бот не может продолжать программу.
+/// `line` - строка скрипта, из которой получилась команда `ptr`,
+/// у бота из байткода и у служебных команд ее нет
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeError {
+    /// Слишком глубокая рекурсия, `ptr` - адрес call, который не влез
+    CallStackOverflow { ptr: i32, line: Option<usize> },
+    /// За ход выполнено `budget` команд, а до действия так и не дошли
+    Livelock {
+        ptr: i32,
+        line: Option<usize>,
+        budget: usize,
+    },
+}
+
+/// По умолчанию ошибки по-русски, на другом языке - через `lang::runtime_error`
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", lang::runtime_error(self, LanguageEnum::Russian))
+    }
 }
 
 #[derive(PartialEq, Debug)]
@@ -35,138 +149,1020 @@ pub enum BotActionEnum {
     TurnRight,
     Nop,
 }
+
+/// Что делать, когда программа дошла до конца
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProgramEndEnum {
+    /// Начать с начала
+    Loop,
+    /// Остановиться, как на `stop`
+    Halt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BotStateEnum {
+    Running,
+    /// Дошел до `stop` или до конца программы с `ProgramEndEnum::Halt`
+    Halted,
+    /// Упал с ошибкой, дальше не ходит
+    Crashed,
+    /// В последний ход потратил все команды и не сделал ни одного действия.
+    /// Если что-то вокруг поменяется, может пойти дальше
+    Blocked,
+}
+/// Где бот встает на паузу в отладчике
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BreakpointEnum {
+    /// Перед первой командой строки скрипта, когда бот приходит на нее с другой строки
+    Line(usize),
+    /// Перед командой с этим адресом
+    Instruction(i32),
+}
+
+/// Что сейчас внутри бота, только для чтения
+#[derive(Debug, PartialEq)]
+pub struct BotView<'a> {
+    /// Адрес команды, которую бот выполнит следующей
+    pub command_ptr: i32,
+    /// Строка скрипта этой команды
+    pub line: Option<usize>,
+    pub state: BotStateEnum,
+    /// Переменные с именами, в порядке первого упоминания в скрипте
+    pub registers: Vec<(&'a str, i32)>,
+    /// Счетчики вложенных repeat, текущий - последний
+    pub counters: &'a [i32],
+    /// Адреса возврата из процедур
+    pub call_stack: &'a [i32],
+    /// Услышанные и еще не прочитанные числа
+    pub inbox: &'a [i32],
+}
+
 #[derive(Debug)]
 pub struct Bot {
     program: Vec<CommandEnum>,
+    /// Место в скрипте для каждой команды из `program`
+    source_map: Vec<Option<Span>>,
     command_ptr: i32,
+    /// Счетчики вложенных repeat, текущий - последний
+    counters: Vec<i32>,
+    /// Адреса возврата из процедур
+    call_stack: Vec<i32>,
+    /// Значения переменных скрипта
+    registers: Vec<i32>,
+    register_names: Vec<String>,
+    /// Скрипт, как его написал пользователь, строки в `source_map` - из него
+    source: String,
+    /// Скрипт в каноническом виде, как его понял парсер
+    canonical_source: String,
+    /// Прогонять ли скомпилированную программу через `optimizer`
+    optimize: bool,
+    /// Синтаксис скрипта, если в нем нет прагмы `#syntax`
+    syntax: SyntaxEnum,
+    /// Случайности бота, поле выдает каждому боту свой поток через `set_seed`
+    rng: StdRng,
+    program_end: ProgramEndEnum,
+    state: BotStateEnum,
+    breakpoints: HashSet<BreakpointEnum>,
+    /// Строка последней выполненной команды, чтобы останавливаться на входе в строку
+    last_line: Option<usize>,
+    /// Что бот услышал и еще не прочитал, `listen` забирает отсюда с начала
+    inbox: Vec<i32>,
+    /// Что бот сказал в этом ходу, поле заберет через `take_said`
+    outbox: Vec<i32>,
+}
+
+impl Default for Bot {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Bot {
     pub fn new() -> Self {
         Self {
             program: vec![],
+            source_map: vec![],
             command_ptr: 0,
+            counters: vec![],
+            call_stack: vec![],
+            registers: vec![],
+            register_names: vec![],
+            source: String::new(),
+            canonical_source: String::new(),
+            optimize: false,
+            syntax: SyntaxEnum::Keywords,
+            rng: StdRng::seed_from_u64(0),
+            program_end: ProgramEndEnum::Loop,
+            state: BotStateEnum::Running,
+            breakpoints: HashSet::new(),
+            last_line: None,
+            inbox: vec![],
+            outbox: vec![],
         }
     }
 
-    pub fn load_from_string(&mut self, src: String) -> Result<(), String> {
-        let s = src.lines();
-        let mut op_codes = self.lex(s)?;
-        op_codes.reverse();
-        self.program = self.parse(&mut op_codes, 0, ParserStateEnum::Root)?;
-        println!("{:?}", self.program);
+    pub fn load_from_string(&mut self, src: String) -> Result<(), Vec<CompileError>> {
+        let program = parser::parse_with_syntax(&src, self.syntax)?;
+        let mut code = codegen::generate(&program);
+        if self.optimize {
+            code = optimizer::optimize(code);
+        }
+        self.canonical_source = printer::print_program(&program);
+        self.source = src;
+        self.load(code.commands, code.source_map, program.registers);
+        Ok(())
+    }
+
+    /// Загрузка уже скомпилированного бота, см. `bytecode::encode`.
+    /// Исходника у такого бота нет, так что и строк скрипта в листинге тоже.
+    /// Канонический скрипт восстанавливается по командам, если это получается, см. `decompiler`
+    pub fn load_from_bytecode(&mut self, bytes: &[u8]) -> Result<(), BytecodeError> {
+        let module = bytecode::decode(bytes)?;
+        self.source.clear();
+        self.canonical_source = decompiler::decompile(&module.commands, &module.registers)
+            .map(|program| printer::print_program(&program))
+            .unwrap_or_default();
+        let source_map = vec![None; module.commands.len()];
+        self.load(module.commands, source_map, module.registers);
         Ok(())
     }
 
-    fn lex(&mut self, src: std::str::Lines) -> Result<Vec<OpCodeEnum>, String> {
-        src.map(|str| match str {
-            "if" => Ok(OpCodeEnum::If),
-            "endIf" => Ok(OpCodeEnum::EndIf),
-            "step" => Ok(OpCodeEnum::Step),
-            "left" => Ok(OpCodeEnum::TurnLeft),
-            "right" => Ok(OpCodeEnum::TurnRight),
-            "leftOrRight" => Ok(OpCodeEnum::TurnRandom),
-            "loop" => Ok(OpCodeEnum::LoopStart),
-            "endLoop" => Ok(OpCodeEnum::LoopEnd),
-            other => Err(format!("Нет такой комманды: {}", other)),
+    /// Программа бота для `bytecode::encode`
+    pub fn to_module(&self) -> Module {
+        Module {
+            commands: self.program.clone(),
+            registers: self.register_names.clone(),
+        }
+    }
+
+    fn load(
+        &mut self,
+        program: Vec<CommandEnum>,
+        source_map: Vec<Option<Span>>,
+        register_names: Vec<String>,
+    ) {
+        self.program = program;
+        self.source_map = source_map;
+        self.command_ptr = 0;
+        self.counters.clear();
+        self.call_stack.clear();
+        self.registers = vec![0; register_names.len()];
+        self.register_names = register_names;
+        self.state = BotStateEnum::Running;
+        self.breakpoints.clear();
+        self.last_line = None;
+        self.inbox.clear();
+        self.outbox.clear();
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    pub fn set_program_end(&mut self, program_end: ProgramEndEnum) {
+        self.program_end = program_end;
+    }
+
+    /// Забирает все, что бот сказал с прошлого раза
+    pub fn take_said(&mut self) -> Vec<i32> {
+        std::mem::take(&mut self.outbox)
+    }
+
+    /// Услышанное за ход добавляется к непрочитанному, пока не наберется `MAX_INBOX`
+    pub fn hear(&mut self, messages: Vec<i32>) {
+        let free = MAX_INBOX.saturating_sub(self.inbox.len());
+        self.inbox.extend(messages.into_iter().take(free));
+    }
+
+    pub fn state(&self) -> BotStateEnum {
+        self.state
+    }
+
+    /// Остановился или упал, в обоих случаях больше не ходит
+    pub fn is_stopped(&self) -> bool {
+        matches!(self.state, BotStateEnum::Halted | BotStateEnum::Crashed)
+    }
+
+    /// Включает оптимизацию для следующих `load_from_string`
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Синтаксис для следующих `load_from_string`
+    pub fn set_syntax(&mut self, syntax: SyntaxEnum) {
+        self.syntax = syntax;
+    }
+
+    /// Скрипт в каноническом виде. У бота из байткода - восстановленный по командам,
+    /// пустой, если восстановить не вышло
+    pub fn canonical_source(&self) -> &str {
+        &self.canonical_source
+    }
+
+    /// Место в скрипте, откуда взялась команда, которую бот выполнит следующей.
+    /// `None`, если бот в конце программы, загружен из байткода или стоит
+    /// на служебном переходе
+    pub fn current_span(&self) -> Option<Span> {
+        self.span_at(self.command_ptr)
+    }
+
+    /// Строка скрипта для `current_span`, считая с 1
+    pub fn current_line(&self) -> Option<usize> {
+        self.current_span().map(|span| span.line)
+    }
+
+    fn span_at(&self, ptr: i32) -> Option<Span> {
+        self.source_map.get(ptr as usize).copied().flatten()
+    }
+
+    pub fn view(&self) -> BotView<'_> {
+        BotView {
+            command_ptr: self.command_ptr,
+            line: self.current_line(),
+            state: self.state,
+            registers: self
+                .register_names
+                .iter()
+                .map(String::as_str)
+                .zip(self.registers.iter().copied())
+                .collect(),
+            counters: &self.counters,
+            call_stack: &self.call_stack,
+            inbox: &self.inbox,
+        }
+    }
+
+    /// Ставит точку останова или снимает, если она уже была.
+    /// При загрузке новой программы все точки снимаются
+    pub fn toggle_breakpoint(&mut self, breakpoint: BreakpointEnum) {
+        if !self.breakpoints.remove(&breakpoint) {
+            self.breakpoints.insert(breakpoint);
+        }
+    }
+
+    pub fn has_breakpoint(&self, breakpoint: BreakpointEnum) -> bool {
+        self.breakpoints.contains(&breakpoint)
+    }
+
+    /// Следующая команда стоит на точке останова
+    pub fn at_breakpoint(&self) -> bool {
+        if self.is_stopped() {
+            return false;
+        }
+        let line = self.current_line();
+        self.breakpoints.iter().any(|breakpoint| match breakpoint {
+            BreakpointEnum::Instruction(ptr) => *ptr == self.command_ptr,
+            BreakpointEnum::Line(bp_line) => line == Some(*bp_line) && self.last_line != line,
         })
-        .collect()
     }
 
-    fn parse(
+    /// Листинг загруженной программы: адреса, переходы и строки скрипта
+    pub fn disassemble(&self) -> Vec<Instruction> {
+        disasm::disassemble(&self.program, &self.source_map, &self.register_names)
+    }
+
+    fn check(&self, condition: &ConditionEnum, sensors: &Sensors) -> bool {
+        match condition {
+            ConditionEnum::Sensor(sensor) => sensors.check_sensor(*sensor),
+            ConditionEnum::Compare(left, compare, right) => {
+                compare.check(self.value(*left), self.value(*right))
+            }
+            ConditionEnum::Heard(value) => self.inbox.contains(&self.value(*value)),
+            ConditionEnum::Not(inner) => !self.check(inner, sensors),
+            ConditionEnum::And(left, right) => {
+                self.check(left, sensors) && self.check(right, sensors)
+            }
+            ConditionEnum::Or(left, right) => {
+                self.check(left, sensors) || self.check(right, sensors)
+            }
+        }
+    }
+
+    fn value(&self, operand: OperandEnum) -> i32 {
+        match operand {
+            OperandEnum::Value(value) => value,
+            OperandEnum::Register(register) => self.registers[register],
+        }
+    }
+
+    /// Один ход: переходы, счетчики и переменные выполняются без траты хода,
+    /// пока не дойдем до действия. Если за `budget` команд действия так и не было,
+    /// бот завис - это `RuntimeError::Livelock` и `BotStateEnum::Blocked`.
+    /// Пустая программа просто стоит
+    pub fn do_tick(
         &mut self,
-        src: &mut Vec<OpCodeEnum>,
-        mut ptr: i32,
-        state: ParserStateEnum,
-    ) -> Result<Vec<CommandEnum>, String> {
-        let mut result = Vec::new();
-        while let Some(code) = src.pop() {
-            match code {
-                OpCodeEnum::TurnLeft => {
-                    result.push(CommandEnum::TurnLeft);
-                    ptr += 1;
-                }
-                OpCodeEnum::TurnRight => {
-                    result.push(CommandEnum::TurnRight);
-                    ptr += 1;
-                }
-                OpCodeEnum::TurnRandom => {
-                    result.push(CommandEnum::TurnRandom);
-                    ptr += 1;
-                }
-                OpCodeEnum::Step => {
-                    result.push(CommandEnum::Step);
-                    ptr += 1;
-                }
-                OpCodeEnum::If => {
-                    ptr += 1; //будет одна инструкция gotoE
-                    let mut part = self.parse(src, ptr, ParserStateEnum::If).unwrap();
-                    result.push(CommandEnum::GoToNE(
-                        (ptr as usize + part.len()).try_into().unwrap(),
-                    ));
-                    result.append(&mut part);
-                }
-                OpCodeEnum::EndIf => {
-                    if state == ParserStateEnum::If {
-                        return Ok(result);
-                    }
-                    return Err("Что то не так с циклами и ифами".to_string());
-                }
-                OpCodeEnum::LoopStart => {
-                    ptr += 1; //будет одна инструкция gotoNE
-                    let mut part = self.parse(src, ptr, ParserStateEnum::Loop).unwrap();
-                    result.push(CommandEnum::GoToNE(
-                        (ptr as usize + part.len() + 1).try_into().unwrap(),
-                    )); //+1 т.к. будет еще одна инструкция goto для цикла
-                    result.append(&mut part);
-                    result.push(CommandEnum::GoTo(ptr - 1));
-                    ptr = (ptr as usize + part.len()).try_into().unwrap();
-                }
-                OpCodeEnum::LoopEnd => {
-                    if state == ParserStateEnum::Loop {
-                        return Ok(result);
-                    }
-                    return Err("Что то не так с циклами и ифами".to_string());
-                }
+        sensors: &Sensors,
+        budget: usize,
+    ) -> Result<BotActionEnum, RuntimeError> {
+        if self.program.is_empty() {
+            return self.do_step(sensors);
+        }
+        for executed in 0..budget {
+            //на точке останова ход кончается, но с той, где бот стоит сейчас, уходим
+            if executed > 0 && self.at_breakpoint() {
+                return Ok(BotActionEnum::Nop);
+            }
+            match self.do_step(sensors)? {
+                BotActionEnum::Nop if self.is_stopped() => break,
+                BotActionEnum::Nop => {}
+                action => return Ok(action),
             }
         }
-        if state != ParserStateEnum::Root {
-            return Err("Похоже есть if или цикл не закрытый".to_string());
+        if self.is_stopped() {
+            return Ok(BotActionEnum::Nop);
         }
-        Ok(result)
+        self.state = BotStateEnum::Blocked;
+        Err(RuntimeError::Livelock {
+            ptr: self.command_ptr,
+            line: self.current_line(),
+            budget,
+        })
     }
 
-    pub fn do_step(&mut self, can_step: bool) -> Option<BotActionEnum> {
-        if self.command_ptr as usize > self.program.len() - 1 {
-            self.command_ptr = 0;
-            return Some(BotActionEnum::Nop);
+    /// Одна команда программы, на каждую уходит ход.
+    /// Остановленный или упавший бот больше ничего не делает
+    pub fn do_step(&mut self, sensors: &Sensors) -> Result<BotActionEnum, RuntimeError> {
+        if self.is_stopped() {
+            return Ok(BotActionEnum::Nop);
+        }
+        let line = self.current_line();
+        let result = self.execute(sensors);
+        self.last_line = line;
+        match result {
+            Err(_) => self.state = BotStateEnum::Crashed,
+            Ok(BotActionEnum::Nop) => {}
+            Ok(_) => self.state = BotStateEnum::Running,
+        }
+        result
+    }
+
+    fn execute(&mut self, sensors: &Sensors) -> Result<BotActionEnum, RuntimeError> {
+        if self.command_ptr as usize >= self.program.len() {
+            match self.program_end {
+                ProgramEndEnum::Loop => self.command_ptr = 0,
+                ProgramEndEnum::Halt => self.state = BotStateEnum::Halted,
+            }
+            return Ok(BotActionEnum::Nop);
         }
 
         let current_command = &self.program[self.command_ptr as usize];
         self.command_ptr += 1;
         match current_command {
-            CommandEnum::Step => Some(BotActionEnum::Step),
-            CommandEnum::TurnLeft => Some(BotActionEnum::TurnLeft),
-            CommandEnum::TurnRight => Some(BotActionEnum::TurnRight),
+            CommandEnum::Step => Ok(BotActionEnum::Step),
+            CommandEnum::TurnLeft => Ok(BotActionEnum::TurnLeft),
+            CommandEnum::TurnRight => Ok(BotActionEnum::TurnRight),
             CommandEnum::TurnRandom => {
-                let mut rnd = rand::thread_rng();
-                if rnd.gen_range(0..2) == 0 {
-                    Some(BotActionEnum::TurnRight)
+                if self.rng.gen_range(0..2) == 0 {
+                    Ok(BotActionEnum::TurnRight)
                 } else {
-                    Some(BotActionEnum::TurnLeft)
+                    Ok(BotActionEnum::TurnLeft)
                 }
-            },
+            }
 
             CommandEnum::GoTo(new_ptr) => {
                 self.command_ptr = *new_ptr;
-                Some(BotActionEnum::Nop)
+                Ok(BotActionEnum::Nop)
+            }
+            CommandEnum::GoToNE(condition, new_ptr) => {
+                if !self.check(condition, sensors) {
+                    self.command_ptr = *new_ptr
+                }
+                Ok(BotActionEnum::Nop)
             }
-            CommandEnum::GoToNE(new_ptr) => {
-                if !can_step {
+            CommandEnum::GoToIf(condition, new_ptr) => {
+                if self.check(condition, sensors) {
                     self.command_ptr = *new_ptr
                 }
-                Some(BotActionEnum::Nop)
+                Ok(BotActionEnum::Nop)
+            }
+            CommandEnum::PushCounter(count) => {
+                self.counters.push(self.value(*count));
+                Ok(BotActionEnum::Nop)
+            }
+            CommandEnum::Set(register, value) => {
+                self.registers[*register] = self.value(*value);
+                Ok(BotActionEnum::Nop)
+            }
+            CommandEnum::Add(register, value) => {
+                self.registers[*register] =
+                    self.registers[*register].wrapping_add(self.value(*value));
+                Ok(BotActionEnum::Nop)
+            }
+            CommandEnum::Sub(register, value) => {
+                self.registers[*register] =
+                    self.registers[*register].wrapping_sub(self.value(*value));
+                Ok(BotActionEnum::Nop)
+            }
+            CommandEnum::Say(value) => {
+                self.outbox.push(self.value(*value));
+                Ok(BotActionEnum::Nop)
+            }
+            CommandEnum::Listen(register) => {
+                if !self.inbox.is_empty() {
+                    self.registers[*register] = self.inbox.remove(0);
+                }
+                Ok(BotActionEnum::Nop)
+            }
+            CommandEnum::Call(new_ptr) => {
+                if self.call_stack.len() >= MAX_CALL_DEPTH {
+                    //упавший бот так и остается на call, который не влез
+                    self.command_ptr -= 1;
+                    return Err(RuntimeError::CallStackOverflow {
+                        ptr: self.command_ptr,
+                        line: self.current_line(),
+                    });
+                }
+                self.call_stack.push(self.command_ptr);
+                self.command_ptr = *new_ptr;
+                Ok(BotActionEnum::Nop)
+            }
+            CommandEnum::Stop => {
+                self.state = BotStateEnum::Halted;
+                Ok(BotActionEnum::Nop)
+            }
+            CommandEnum::Return => {
+                self.command_ptr = self.call_stack.pop().unwrap_or(0);
+                Ok(BotActionEnum::Nop)
+            }
+            CommandEnum::CounterNext(new_ptr) => {
+                match self.counters.last_mut() {
+                    Some(counter) if *counter > 0 => *counter -= 1,
+                    _ => {
+                        self.counters.pop();
+                        self.command_ptr = *new_ptr;
+                    }
+                }
+                Ok(BotActionEnum::Nop)
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Span;
+
+    const FREE: ConditionEnum = ConditionEnum::Sensor(SensorEnum::Free);
+
+    fn sensors(free_ahead: bool) -> Sensors {
+        Sensors {
+            free_ahead,
+            free_behind: true,
+            wall_left: false,
+            wall_right: false,
+            bot_ahead: false,
+            at_edge: false,
+            direction: DirectionEnum::Up,
+        }
+    }
+
+    #[test]
+    fn sensor_conditions() {
+        let mut bot = Bot::new();
+        bot.load_from_string(
+            "if wallLeft right else loop facingNorth left endLoop endIf".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            bot.program,
+            vec![
+                CommandEnum::GoToNE(ConditionEnum::Sensor(SensorEnum::WallLeft), 3),
+                CommandEnum::TurnRight,
+                CommandEnum::GoTo(6),
+                CommandEnum::GoToNE(
+                    ConditionEnum::Sensor(SensorEnum::Facing(DirectionEnum::Up)),
+                    6
+                ),
+                CommandEnum::TurnLeft,
+                CommandEnum::GoTo(3)
+            ]
+        );
+        let mut view = sensors(true);
+        view.wall_left = true;
+        assert_eq!(bot.do_step(&view), Ok(BotActionEnum::Nop));
+        assert_eq!(bot.do_step(&view), Ok(BotActionEnum::TurnRight));
+    }
+
+    #[test]
+    fn nested_error_is_returned_with_position() {
+        let mut bot = Bot::new();
+        let errors = bot
+            .load_from_string("loop\nif\njump\nendIf\nendLoop".to_string())
+            .unwrap_err();
+        assert_eq!(
+            errors,
+            vec![CompileError::UnknownCommand {
+                token: "jump".to_string(),
+                span: Span {
+                    line: 3,
+                    column: 1,
+                    start: 8,
+                    end: 12
+                }
+            }]
+        );
+    }
+
+    #[test]
+    fn collects_several_errors() {
+        let mut bot = Bot::new();
+        let errors = bot
+            .load_from_string("jump\nendIf\nloop\nstep".to_string())
+            .unwrap_err();
+        assert_eq!(errors.len(), 3);
+        assert!(matches!(errors[0], CompileError::UnknownCommand { .. }));
+        assert!(matches!(errors[1], CompileError::UnexpectedBlockEnd { .. }));
+        assert!(matches!(errors[2], CompileError::UnclosedBlock { .. }));
+        assert_eq!((errors[1].line(), errors[1].token()), (2, "endIf"));
+        assert_eq!((errors[2].line(), errors[2].token()), (3, "loop"));
+    }
+
+    #[test]
+    fn free_form_source() {
+        let src = "# бот ходит по кругу\r\n\r\nloop   \r\n    step left # налево\r\n\tright\r\nendLoop\r\n";
+        let mut bot = Bot::new();
+        bot.load_from_string(src.to_string()).unwrap();
+        assert_eq!(
+            bot.program,
+            vec![
+                CommandEnum::GoToNE(FREE, 5),
+                CommandEnum::Step,
+                CommandEnum::TurnLeft,
+                CommandEnum::TurnRight,
+                CommandEnum::GoTo(0)
+            ]
+        );
+    }
+
+    #[test]
+    fn error_column_after_indent() {
+        let mut bot = Bot::new();
+        let errors = bot
+            .load_from_string("loop\n  step jump#\nendLoop".to_string())
+            .unwrap_err();
+        assert_eq!((errors[0].line(), errors[0].column()), (2, 8));
+        assert_eq!(errors[0].token(), "jump");
+    }
+
+    #[test]
+    fn empty_program_does_nothing() {
+        let mut bot = Bot::new();
+        bot.load_from_string("  # пусто\n\n".to_string()).unwrap();
+        assert_eq!(bot.do_step(&sensors(true)), Ok(BotActionEnum::Nop));
+    }
+
+    #[test]
+    fn condition_expressions() {
+        let mut bot = Bot::new();
+        bot.load_from_string(
+            "loop free and not botAhead step endLoop if not (wallLeft or wallRight) left endIf"
+                .to_string(),
+        )
+        .unwrap();
+        let wall_left = ConditionEnum::Sensor(SensorEnum::WallLeft);
+        let wall_right = ConditionEnum::Sensor(SensorEnum::WallRight);
+        let bot_ahead = ConditionEnum::Sensor(SensorEnum::BotAhead);
+        assert_eq!(
+            bot.program,
+            vec![
+                CommandEnum::GoToNE(
+                    ConditionEnum::And(
+                        Box::new(FREE),
+                        Box::new(ConditionEnum::Not(Box::new(bot_ahead)))
+                    ),
+                    3
+                ),
+                CommandEnum::Step,
+                CommandEnum::GoTo(0),
+                CommandEnum::GoToIf(
+                    ConditionEnum::Or(Box::new(wall_left), Box::new(wall_right)),
+                    5
+                ),
+                CommandEnum::TurnLeft
+            ]
+        );
+    }
+
+    #[test]
+    fn condition_precedence() {
+        let mut sensors = sensors(false);
+        let mut bot = Bot::new();
+        bot.load_from_string("if not free or wallLeft and wallRight step endIf".to_string())
+            .unwrap();
+        // not free = true, значит (not free) or (...) = true
+        assert_eq!(bot.do_step(&sensors), Ok(BotActionEnum::Nop));
+        assert_eq!(bot.do_step(&sensors), Ok(BotActionEnum::Step));
+        sensors.free_ahead = true;
+        sensors.wall_left = true;
+        assert_eq!(bot.do_step(&sensors), Ok(BotActionEnum::Nop));
+        assert_eq!(bot.do_step(&sensors), Ok(BotActionEnum::Nop));
+    }
+
+    #[test]
+    fn broken_conditions() {
+        let mut bot = Bot::new();
+        let errors = bot
+            .load_from_string("if free and step endIf\nloop (free endLoop\nif not".to_string())
+            .unwrap_err();
+        let found: Vec<(usize, &str)> = errors.iter().map(|e| (e.line(), e.token())).collect();
+        assert_eq!(found, vec![(1, "step"), (2, "("), (3, "not"), (3, "if")]);
+        assert!(matches!(errors[1], CompileError::UnclosedBlock { .. }));
+    }
+
+    #[test]
+    fn repeat_counts() {
+        let mut bot = Bot::new();
+        bot.load_from_string("repeat 2 repeat 3 step endRepeat left endRepeat".to_string())
+            .unwrap();
+        assert_eq!(
+            bot.program,
+            vec![
+                CommandEnum::PushCounter(OperandEnum::Value(2)),
+                CommandEnum::CounterNext(8),
+                CommandEnum::PushCounter(OperandEnum::Value(3)),
+                CommandEnum::CounterNext(6),
+                CommandEnum::Step,
+                CommandEnum::GoTo(3),
+                CommandEnum::TurnLeft,
+                CommandEnum::GoTo(1),
+            ]
+        );
+        let mut actions = Vec::new();
+        while (bot.command_ptr as usize) < bot.program.len() {
+            match bot.do_step(&sensors(true)).unwrap() {
+                BotActionEnum::Nop => {}
+                action => actions.push(action),
+            }
+        }
+        use BotActionEnum::{Step, TurnLeft};
+        assert_eq!(
+            actions,
+            vec![Step, Step, Step, TurnLeft, Step, Step, Step, TurnLeft]
+        );
+        assert!(bot.counters.is_empty());
+    }
+
+    #[test]
+    fn repeat_without_count() {
+        let mut bot = Bot::new();
+        let errors = bot
+            .load_from_string("repeat step endRepeat 3".to_string())
+            .unwrap_err();
+        assert!(matches!(errors[0], CompileError::ExpectedNumber { .. }));
+        assert!(matches!(errors[1], CompileError::UnknownCommand { .. }));
+    }
+
+    #[test]
+    fn procedures() {
+        let mut bot = Bot::new();
+        bot.load_from_string(
+            "call turnAround step\nproc turnAround repeat 2 left endRepeat endProc".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            bot.program,
+            vec![
+                CommandEnum::Call(3),
+                CommandEnum::Step,
+                CommandEnum::GoTo(8),
+                CommandEnum::PushCounter(OperandEnum::Value(2)),
+                CommandEnum::CounterNext(7),
+                CommandEnum::TurnLeft,
+                CommandEnum::GoTo(4),
+                CommandEnum::Return,
+            ]
+        );
+        let mut actions = Vec::new();
+        while actions.len() < 3 {
+            match bot.do_step(&sensors(true)).unwrap() {
+                BotActionEnum::Nop => {}
+                action => actions.push(action),
+            }
+        }
+        use BotActionEnum::{Step, TurnLeft};
+        assert_eq!(actions, vec![TurnLeft, TurnLeft, Step]);
+        assert!(bot.call_stack.is_empty());
+    }
+
+    #[test]
+    fn procedure_errors() {
+        let mut bot = Bot::new();
+        let errors = bot
+            .load_from_string(
+                "call nowhere\nproc a endProc\nproc a endProc\nloop proc b endProc endLoop\ncall"
+                    .to_string(),
+            )
+            .unwrap_err();
+        let found: Vec<(usize, &str)> = errors.iter().map(|e| (e.line(), e.token())).collect();
+        assert_eq!(
+            found,
+            vec![(3, "a"), (4, "proc"), (5, "call"), (1, "nowhere")]
+        );
+        assert!(matches!(errors[3], CompileError::UndefinedProc { .. }));
+    }
+
+    #[test]
+    fn tick_runs_until_action() {
+        let mut bot = Bot::new();
+        bot.load_from_string(
+            "loop repeat 3 inc x endRepeat if x > 5 step endIf endLoop".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            bot.do_tick(&sensors(true), DEFAULT_TICK_BUDGET),
+            Ok(BotActionEnum::Step)
+        );
+        assert_eq!(bot.registers, vec![6]);
+        //впереди занято, из цикла выходим и крутимся по программе без действий
+        assert!(matches!(
+            bot.do_tick(&sensors(false), DEFAULT_TICK_BUDGET),
+            Err(RuntimeError::Livelock {
+                budget: DEFAULT_TICK_BUDGET,
+                ..
+            })
+        ));
+
+        let mut bot = Bot::new();
+        bot.load_from_string("set x 1".to_string()).unwrap();
+        assert!(matches!(
+            bot.do_tick(&sensors(true), 10),
+            Err(RuntimeError::Livelock { budget: 10, .. })
+        ));
+        bot.load_from_string("".to_string()).unwrap();
+        assert_eq!(bot.do_tick(&sensors(true), 10), Ok(BotActionEnum::Nop));
+    }
+
+    #[test]
+    fn stop_and_program_end() {
+        let mut bot = Bot::new();
+        bot.load_from_string("step if wallLeft stop endIf left".to_string())
+            .unwrap();
+        let mut view = sensors(true);
+        view.wall_left = true;
+        assert_eq!(bot.do_tick(&view, 10), Ok(BotActionEnum::Step));
+        assert_eq!(bot.do_tick(&view, 10), Ok(BotActionEnum::Nop));
+        assert_eq!(bot.state(), BotStateEnum::Halted);
+        assert_eq!(bot.do_step(&sensors(true)), Ok(BotActionEnum::Nop));
+
+        //без stop программа по умолчанию идет по кругу
+        bot.load_from_string("step".to_string()).unwrap();
+        assert_eq!(bot.do_step(&view), Ok(BotActionEnum::Step));
+        assert_eq!(bot.do_step(&view), Ok(BotActionEnum::Nop));
+        assert_eq!(bot.do_step(&view), Ok(BotActionEnum::Step));
+        assert_eq!(bot.state(), BotStateEnum::Running);
+
+        bot.set_program_end(ProgramEndEnum::Halt);
+        bot.load_from_string("call turn proc turn left endProc".to_string())
+            .unwrap();
+        assert_eq!(bot.do_tick(&view, 10), Ok(BotActionEnum::TurnLeft));
+        assert_eq!(bot.do_tick(&view, 10), Ok(BotActionEnum::Nop));
+        assert_eq!(bot.state(), BotStateEnum::Halted);
+        bot.load_from_string("".to_string()).unwrap();
+        assert_eq!(bot.do_tick(&view, 10), Ok(BotActionEnum::Nop));
+        assert_eq!(bot.state(), BotStateEnum::Halted);
+    }
+
+    #[test]
+    fn blocked_and_crashed() {
+        let mut bot = Bot::new();
+        bot.load_from_string("loop not free endLoop step".to_string())
+            .unwrap();
+        assert!(bot.do_tick(&sensors(false), 10).is_err());
+        assert_eq!(bot.state(), BotStateEnum::Blocked);
+        assert_eq!(bot.do_tick(&sensors(true), 10), Ok(BotActionEnum::Step));
+        assert_eq!(bot.state(), BotStateEnum::Running);
+
+        bot.load_from_string("proc again call again endProc call again".to_string())
+            .unwrap();
+        assert!(matches!(
+            bot.do_tick(&sensors(true), 1000),
+            Err(RuntimeError::CallStackOverflow { .. })
+        ));
+        assert_eq!(bot.state(), BotStateEnum::Crashed);
+        assert_eq!(bot.do_tick(&sensors(true), 1000), Ok(BotActionEnum::Nop));
+    }
+
+    #[test]
+    fn endless_recursion() {
+        let mut bot = Bot::new();
+        bot.load_from_string("proc again call again endProc call again".to_string())
+            .unwrap();
+        let result = (0..=MAX_CALL_DEPTH).try_for_each(|_| bot.do_step(&sensors(true)).map(|_| ()));
+        assert_eq!(
+            result,
+            Err(RuntimeError::CallStackOverflow {
+                ptr: 2,
+                line: Some(1)
+            })
+        );
+    }
+
+    #[test]
+    fn current_line_follows_source() {
+        let mut bot = Bot::new();
+        bot.load_from_string(
+            "# круг\nloop\n  step\nendLoop\nproc again\n  call again\nendProc".to_string(),
+        )
+        .unwrap();
+        assert_eq!(bot.current_line(), Some(2));
+        assert_eq!(bot.do_step(&sensors(true)), Ok(BotActionEnum::Nop));
+        assert_eq!(bot.current_line(), Some(3));
+        assert_eq!(bot.current_span().map(|span| span.column), Some(3));
+        assert_eq!(bot.do_step(&sensors(true)), Ok(BotActionEnum::Step));
+        //переход назад в цикл относится к строке loop
+        assert_eq!(bot.current_line(), Some(2));
+        let err = bot.do_tick(&sensors(false), 10).unwrap_err();
+        assert_eq!(err.to_string().split(':').next(), Some("Строка 2"));
+
+        bot.load_from_string("proc again\n  call again\nendProc\ncall again".to_string())
+            .unwrap();
+        let err = bot.do_tick(&sensors(true), 1000).unwrap_err();
+        assert!(err.to_string().starts_with("Строка 2:"));
+        assert_eq!(bot.current_line(), Some(2));
+
+        let bytes = bytecode::encode(&bot.to_module()).unwrap();
+        bot.load_from_bytecode(&bytes).unwrap();
+        assert_eq!((bot.current_line(), bot.source()), (None, ""));
+        let err = bot.do_tick(&sensors(true), 1000).unwrap_err();
+        assert!(err.to_string().starts_with("Инструкция 2:"));
+    }
+
+    #[test]
+    fn breakpoints_and_view() {
+        let mut bot = Bot::new();
+        bot.load_from_string("set n 2\nloop n > 0\n  dec n\nendLoop\nleft".to_string())
+            .unwrap();
+        bot.toggle_breakpoint(BreakpointEnum::Line(2));
+        bot.toggle_breakpoint(BreakpointEnum::Instruction(4));
+        assert!(!bot.at_breakpoint());
+        //строка 2 - это и условие цикла, и переход назад, но встаем один раз за круг,
+        //когда приходим на нее из тела цикла
+        let mut stops = vec![];
+        for _ in 0..8 {
+            bot.do_tick(&sensors(true), 100).unwrap();
+            stops.push((bot.view().command_ptr, bot.view().line));
+        }
+        assert_eq!(
+            stops,
+            vec![
+                (1, Some(2)),
+                (3, Some(2)),
+                (3, Some(2)),
+                (4, Some(5)),
+                (5, None),
+                (1, Some(2)),
+                (3, Some(2)),
+                (3, Some(2))
+            ]
+        );
+        let view = bot.view();
+        assert_eq!(view.registers, vec![("n", 0)]);
+        assert!(view.counters.is_empty() && view.call_stack.is_empty());
+        assert_eq!(view.state, BotStateEnum::Running);
+
+        bot.toggle_breakpoint(BreakpointEnum::Line(2));
+        assert!(!bot.has_breakpoint(BreakpointEnum::Line(2)));
+        bot.load_from_string("left".to_string()).unwrap();
+        assert!(!bot.has_breakpoint(BreakpointEnum::Instruction(4)));
+    }
+
+    #[test]
+    fn registers() {
+        let mut bot = Bot::new();
+        bot.load_from_string(
+            "set n 0\nloop n<3 inc n step endLoop\nset k n add k 10 sub k n dec k\nif k == 9 and n>=3 left endIf"
+                .to_string(),
+        )
+        .unwrap();
+        assert_eq!(bot.register_names, vec!["n".to_string(), "k".to_string()]);
+        let mut actions = Vec::new();
+        while (bot.command_ptr as usize) < bot.program.len() {
+            match bot.do_step(&sensors(true)).unwrap() {
+                BotActionEnum::Nop => {}
+                action => actions.push(action),
+            }
+        }
+        use BotActionEnum::{Step, TurnLeft};
+        assert_eq!(actions, vec![Step, Step, Step, TurnLeft]);
+        assert_eq!(bot.registers, vec![3, 9]);
+    }
+
+    #[test]
+    fn register_errors() {
+        let mut bot = Bot::new();
+        let errors = bot
+            .load_from_string("set\nadd x\nif x > step endIf\n= 1".to_string())
+            .unwrap_err();
+        let found: Vec<(usize, &str)> = errors.iter().map(|e| (e.line(), e.token())).collect();
+        assert_eq!(
+            found,
+            vec![
+                (1, "set"),
+                (1, "set"),
+                (2, "add"),
+                (3, ">"),
+                (4, "="),
+                (4, "1")
+            ]
+        );
+        let names: String = (0..=MAX_REGISTERS)
+            .map(|idx| format!("inc r{} ", idx))
+            .collect();
+        let errors = bot.load_from_string(names).unwrap_err();
+        assert!(matches!(
+            errors[..],
+            [CompileError::TooManyRegisters { .. }]
+        ));
+    }
+
+    #[test]
+    fn if_else() {
+        let mut bot = Bot::new();
+        bot.load_from_string("if step else left endIf right".to_string())
+            .unwrap();
+        assert_eq!(
+            bot.program,
+            vec![
+                CommandEnum::GoToNE(FREE, 3),
+                CommandEnum::Step,
+                CommandEnum::GoTo(4),
+                CommandEnum::TurnLeft,
+                CommandEnum::TurnRight
+            ]
+        );
+    }
+
+    #[test]
+    fn jumps_after_nested_blocks() {
+        let mut bot = Bot::new();
+        bot.load_from_string("if step endIf loop left endLoop".to_string())
+            .unwrap();
+        assert_eq!(
+            bot.program,
+            vec![
+                CommandEnum::GoToNE(FREE, 2),
+                CommandEnum::Step,
+                CommandEnum::GoToNE(FREE, 5),
+                CommandEnum::TurnLeft,
+                CommandEnum::GoTo(2)
+            ]
+        );
+    }
+
+    #[test]
+    fn else_outside_if() {
+        let mut bot = Bot::new();
+        let errors = bot
+            .load_from_string("loop else endLoop if else else endIf".to_string())
+            .unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert_eq!((errors[0].column(), errors[0].token()), (6, "else"));
+        assert_eq!((errors[1].column(), errors[1].token()), (27, "else"));
+    }
+
+    #[test]
+    fn valid_program_compiles() {
+        let mut bot = Bot::new();
+        bot.load_from_string("loop\nstep\nendLoop\nleft".to_string())
+            .unwrap();
+        assert_eq!(
+            bot.program,
+            vec![
+                CommandEnum::GoToNE(FREE, 3),
+                CommandEnum::Step,
+                CommandEnum::GoTo(0),
+                CommandEnum::TurnLeft
+            ]
+        );
+    }
+
+    #[test]
+    fn say_and_listen() {
+        let mut bot = Bot::new();
+        bot.load_from_string(
+            "#lang ru\nсказать 7\nесли услышал 2 слушать x сказать x конецЕсли\nстоп".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            bot.canonical_source(),
+            "say 7\nif heard 2\n    listen x\n    say x\nendIf\nstop\n"
+        );
+        bot.hear(vec![5, 2]);
+        assert_eq!(bot.do_tick(&sensors(true), 10), Ok(BotActionEnum::Nop));
+        //listen забирает самое старое, а не то, которое проверял if
+        assert_eq!(bot.take_said(), vec![7, 5]);
+        assert_eq!(bot.view().registers, vec![("x", 5)]);
+        assert_eq!(bot.view().inbox, &[2]);
+        assert!(bot.take_said().is_empty());
+
+        //непрочитанное остается, новое дописывается в конец
+        bot.hear((0..20).collect());
+        assert_eq!(bot.view().inbox.len(), MAX_INBOX);
+        assert_eq!(bot.view().inbox[..3], [2, 0, 1]);
+    }
+}
@@ -0,0 +1,575 @@
+use crate::ast::{CompareEnum, ConditionEnum, OperandEnum, SensorEnum};
+use crate::bot::{CommandEnum, MAX_CONDITION_DEPTH, MAX_REGISTERS};
+use crate::field::DirectionEnum;
+use crate::lang::{self, LanguageEnum};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::fmt;
+
+/// С этих байт начинается любой байткод
+pub const MAGIC: &[u8; 4] = b"BOTC";
+
+/// Версия формата, меняется при любом несовместимом изменении команд
+pub const VERSION: u8 = 1;
+
+/// Скомпилированная программа без исходника: команды и имена переменных
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+    pub commands: Vec<CommandEnum>,
+    pub registers: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BytecodeError {
+    /// Не base64
+    BadBase64,
+    /// В начале нет `MAGIC`
+    BadMagic,
+    /// Байткод от другой версии
+    UnsupportedVersion { version: u8 },
+    /// Байты кончились посреди команды, `offset` - где именно
+    UnexpectedEnd { offset: usize },
+    /// Неизвестный код команды, датчика и т.п.
+    UnknownCode { offset: usize, code: u8 },
+    /// Имя переменной не в utf-8
+    BadName { offset: usize },
+    /// Имя переменной длиннее, чем влезает в байткод
+    NameTooLong { register: usize },
+    /// После последней команды еще что-то есть
+    TrailingBytes { offset: usize },
+    /// Слишком глубоко вложенное условие
+    ConditionTooDeep { offset: usize },
+    /// Переменных больше `MAX_REGISTERS`
+    TooManyRegisters { count: usize },
+    /// Переход за пределы программы
+    BadTarget { ptr: usize, target: i32 },
+    /// Команда ссылается на переменную, которой нет
+    BadRegister { ptr: usize, register: usize },
+}
+
+/// По умолчанию ошибки по-русски, на другом языке - через `lang::bytecode_error`
+impl fmt::Display for BytecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", lang::bytecode_error(self, LanguageEnum::Russian))
+    }
+}
+
+/// Байткод: `MAGIC`, версия, число переменных и их имена (длина u16 и utf-8),
+/// число команд (u32) и сами команды. Числа little-endian.
+/// Имя переменной, длина которого не влезает в u16, - ошибка, а не обрезанная длина
+pub fn encode(module: &Module) -> Result<Vec<u8>, BytecodeError> {
+    let mut out = MAGIC.to_vec();
+    out.push(VERSION);
+    out.push(module.registers.len() as u8);
+    for (register, name) in module.registers.iter().enumerate() {
+        let len = u16::try_from(name.len()).map_err(|_| BytecodeError::NameTooLong { register })?;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(name.as_bytes());
+    }
+    out.extend_from_slice(&(module.commands.len() as u32).to_le_bytes());
+    for command in module.commands.iter() {
+        encode_command(command, &mut out);
+    }
+    Ok(out)
+}
+
+/// Байткод текстом, чтобы его можно было положить в файл уровня или переслать
+pub fn to_base64(bytes: &[u8]) -> String {
+    STANDARD.encode(bytes)
+}
+
+/// Читает байткод и проверяет, что все переходы и переменные на месте
+pub fn decode(bytes: &[u8]) -> Result<Module, BytecodeError> {
+    let mut reader = Reader { bytes, offset: 0 };
+    if reader.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+        return Err(BytecodeError::BadMagic);
+    }
+    let version = reader.u8()?;
+    if version != VERSION {
+        return Err(BytecodeError::UnsupportedVersion { version });
+    }
+    let register_count = reader.u8()? as usize;
+    if register_count > MAX_REGISTERS {
+        return Err(BytecodeError::TooManyRegisters {
+            count: register_count,
+        });
+    }
+    let mut registers = Vec::with_capacity(register_count);
+    for _ in 0..register_count {
+        let len = reader.u16()? as usize;
+        let offset = reader.offset;
+        let name = std::str::from_utf8(reader.take(len)?)
+            .map_err(|_| BytecodeError::BadName { offset })?;
+        registers.push(name.to_string());
+    }
+    let command_count = reader.u32()? as usize;
+    let mut commands = Vec::new();
+    for _ in 0..command_count {
+        commands.push(reader.command()?);
+    }
+    if reader.offset != bytes.len() {
+        return Err(BytecodeError::TrailingBytes {
+            offset: reader.offset,
+        });
+    }
+    let module = Module {
+        commands,
+        registers,
+    };
+    check(&module)?;
+    Ok(module)
+}
+
+pub fn from_base64(src: &str) -> Result<Vec<u8>, BytecodeError> {
+    STANDARD
+        .decode(src.trim())
+        .map_err(|_| BytecodeError::BadBase64)
+}
+
+/// Похоже ли на байткод в base64, а не на скрипт
+pub fn is_base64(src: &str) -> bool {
+    src.trim_start().starts_with(&STANDARD.encode(MAGIC)[..5])
+}
+
+/// Переходы - только внутрь программы или сразу за ее конец, переменные - только существующие
+fn check(module: &Module) -> Result<(), BytecodeError> {
+    let len = module.commands.len() as i32;
+    for (ptr, command) in module.commands.iter().enumerate() {
+        if let Some(target) = command.target() {
+            if target < 0 || target > len {
+                return Err(BytecodeError::BadTarget { ptr, target });
+            }
+        }
+        let mut registers = vec![];
+        match command {
+            CommandEnum::GoToNE(condition, _) | CommandEnum::GoToIf(condition, _) => {
+                condition_registers(condition, &mut registers)
+            }
+            CommandEnum::PushCounter(operand) => operand_registers(*operand, &mut registers),
+            CommandEnum::Set(register, operand)
+            | CommandEnum::Add(register, operand)
+            | CommandEnum::Sub(register, operand) => {
+                registers.push(*register);
+                operand_registers(*operand, &mut registers);
+            }
+            CommandEnum::Say(operand) => operand_registers(*operand, &mut registers),
+            CommandEnum::Listen(register) => registers.push(*register),
+            _ => {}
+        }
+        if let Some(register) = registers
+            .into_iter()
+            .find(|register| *register >= module.registers.len())
+        {
+            return Err(BytecodeError::BadRegister { ptr, register });
+        }
+    }
+    Ok(())
+}
+
+fn condition_registers(condition: &ConditionEnum, registers: &mut Vec<usize>) {
+    match condition {
+        ConditionEnum::Sensor(_) => {}
+        ConditionEnum::Compare(left, _, right) => {
+            operand_registers(*left, registers);
+            operand_registers(*right, registers);
+        }
+        ConditionEnum::Heard(value) => operand_registers(*value, registers),
+        ConditionEnum::Not(inner) => condition_registers(inner, registers),
+        ConditionEnum::And(left, right) | ConditionEnum::Or(left, right) => {
+            condition_registers(left, registers);
+            condition_registers(right, registers);
+        }
+    }
+}
+
+fn operand_registers(operand: OperandEnum, registers: &mut Vec<usize>) {
+    if let OperandEnum::Register(register) = operand {
+        registers.push(register);
+    }
+}
+
+fn encode_command(command: &CommandEnum, out: &mut Vec<u8>) {
+    match command {
+        CommandEnum::Step => out.push(0),
+        CommandEnum::TurnLeft => out.push(1),
+        CommandEnum::TurnRight => out.push(2),
+        CommandEnum::TurnRandom => out.push(3),
+        CommandEnum::GoTo(target) => {
+            out.push(4);
+            out.extend_from_slice(&target.to_le_bytes());
+        }
+        CommandEnum::GoToNE(condition, target) | CommandEnum::GoToIf(condition, target) => {
+            out.push(if matches!(command, CommandEnum::GoToNE(..)) {
+                5
+            } else {
+                6
+            });
+            encode_condition(condition, out);
+            out.extend_from_slice(&target.to_le_bytes());
+        }
+        CommandEnum::PushCounter(count) => {
+            out.push(7);
+            encode_operand(*count, out);
+        }
+        CommandEnum::CounterNext(target) => {
+            out.push(8);
+            out.extend_from_slice(&target.to_le_bytes());
+        }
+        CommandEnum::Call(target) => {
+            out.push(9);
+            out.extend_from_slice(&target.to_le_bytes());
+        }
+        CommandEnum::Return => out.push(10),
+        CommandEnum::Stop => out.push(14),
+        CommandEnum::Set(register, value)
+        | CommandEnum::Add(register, value)
+        | CommandEnum::Sub(register, value) => {
+            out.push(match command {
+                CommandEnum::Set(..) => 11,
+                CommandEnum::Add(..) => 12,
+                _ => 13,
+            });
+            out.push(*register as u8);
+            encode_operand(*value, out);
+        }
+        CommandEnum::Say(value) => {
+            out.push(15);
+            encode_operand(*value, out);
+        }
+        CommandEnum::Listen(register) => {
+            out.push(16);
+            out.push(*register as u8);
+        }
+    }
+}
+
+fn encode_operand(operand: OperandEnum, out: &mut Vec<u8>) {
+    match operand {
+        OperandEnum::Value(value) => {
+            out.push(0);
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        OperandEnum::Register(register) => {
+            out.push(1);
+            out.push(register as u8);
+        }
+    }
+}
+
+fn encode_condition(condition: &ConditionEnum, out: &mut Vec<u8>) {
+    match condition {
+        ConditionEnum::Sensor(sensor) => {
+            out.push(0);
+            out.push(match sensor {
+                SensorEnum::Free => 0,
+                SensorEnum::FreeBehind => 1,
+                SensorEnum::WallLeft => 2,
+                SensorEnum::WallRight => 3,
+                SensorEnum::BotAhead => 4,
+                SensorEnum::AtEdge => 5,
+                SensorEnum::Facing(DirectionEnum::Up) => 6,
+                SensorEnum::Facing(DirectionEnum::Right) => 7,
+                SensorEnum::Facing(DirectionEnum::Down) => 8,
+                SensorEnum::Facing(DirectionEnum::Left) => 9,
+            });
+        }
+        ConditionEnum::Compare(left, compare, right) => {
+            out.push(1);
+            encode_operand(*left, out);
+            out.push(match compare {
+                CompareEnum::Less => 0,
+                CompareEnum::LessOrEqual => 1,
+                CompareEnum::Greater => 2,
+                CompareEnum::GreaterOrEqual => 3,
+                CompareEnum::Equal => 4,
+                CompareEnum::NotEqual => 5,
+            });
+            encode_operand(*right, out);
+        }
+        ConditionEnum::Not(inner) => {
+            out.push(2);
+            encode_condition(inner, out);
+        }
+        ConditionEnum::Heard(value) => {
+            out.push(5);
+            encode_operand(*value, out);
+        }
+        ConditionEnum::And(left, right) | ConditionEnum::Or(left, right) => {
+            out.push(if matches!(condition, ConditionEnum::And(..)) {
+                3
+            } else {
+                4
+            });
+            encode_condition(left, out);
+            encode_condition(right, out);
+        }
+    }
+}
+
+/// Читает байты по порядку, `offset` - сколько уже прочитано
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], BytecodeError> {
+        let end = self.offset + len;
+        if end > self.bytes.len() {
+            return Err(BytecodeError::UnexpectedEnd {
+                offset: self.bytes.len(),
+            });
+        }
+        let result = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(result)
+    }
+
+    fn u8(&mut self) -> Result<u8, BytecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, BytecodeError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, BytecodeError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i32(&mut self) -> Result<i32, BytecodeError> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn unknown(&self, code: u8) -> BytecodeError {
+        BytecodeError::UnknownCode {
+            offset: self.offset - 1,
+            code,
+        }
+    }
+
+    fn command(&mut self) -> Result<CommandEnum, BytecodeError> {
+        let code = self.u8()?;
+        Ok(match code {
+            0 => CommandEnum::Step,
+            1 => CommandEnum::TurnLeft,
+            2 => CommandEnum::TurnRight,
+            3 => CommandEnum::TurnRandom,
+            4 => CommandEnum::GoTo(self.i32()?),
+            5 => {
+                let condition = self.condition(0)?;
+                CommandEnum::GoToNE(condition, self.i32()?)
+            }
+            6 => {
+                let condition = self.condition(0)?;
+                CommandEnum::GoToIf(condition, self.i32()?)
+            }
+            7 => CommandEnum::PushCounter(self.operand()?),
+            8 => CommandEnum::CounterNext(self.i32()?),
+            9 => CommandEnum::Call(self.i32()?),
+            10 => CommandEnum::Return,
+            11 => CommandEnum::Set(self.u8()? as usize, self.operand()?),
+            12 => CommandEnum::Add(self.u8()? as usize, self.operand()?),
+            13 => CommandEnum::Sub(self.u8()? as usize, self.operand()?),
+            14 => CommandEnum::Stop,
+            15 => CommandEnum::Say(self.operand()?),
+            16 => CommandEnum::Listen(self.u8()? as usize),
+            code => return Err(self.unknown(code)),
+        })
+    }
+
+    fn operand(&mut self) -> Result<OperandEnum, BytecodeError> {
+        match self.u8()? {
+            0 => Ok(OperandEnum::Value(self.i32()?)),
+            1 => Ok(OperandEnum::Register(self.u8()? as usize)),
+            code => Err(self.unknown(code)),
+        }
+    }
+
+    fn condition(&mut self, depth: usize) -> Result<ConditionEnum, BytecodeError> {
+        if depth > MAX_CONDITION_DEPTH {
+            return Err(BytecodeError::ConditionTooDeep {
+                offset: self.offset,
+            });
+        }
+        Ok(match self.u8()? {
+            0 => ConditionEnum::Sensor(match self.u8()? {
+                0 => SensorEnum::Free,
+                1 => SensorEnum::FreeBehind,
+                2 => SensorEnum::WallLeft,
+                3 => SensorEnum::WallRight,
+                4 => SensorEnum::BotAhead,
+                5 => SensorEnum::AtEdge,
+                6 => SensorEnum::Facing(DirectionEnum::Up),
+                7 => SensorEnum::Facing(DirectionEnum::Right),
+                8 => SensorEnum::Facing(DirectionEnum::Down),
+                9 => SensorEnum::Facing(DirectionEnum::Left),
+                code => return Err(self.unknown(code)),
+            }),
+            1 => {
+                let left = self.operand()?;
+                let compare = match self.u8()? {
+                    0 => CompareEnum::Less,
+                    1 => CompareEnum::LessOrEqual,
+                    2 => CompareEnum::Greater,
+                    3 => CompareEnum::GreaterOrEqual,
+                    4 => CompareEnum::Equal,
+                    5 => CompareEnum::NotEqual,
+                    code => return Err(self.unknown(code)),
+                };
+                ConditionEnum::Compare(left, compare, self.operand()?)
+            }
+            2 => ConditionEnum::Not(Box::new(self.condition(depth + 1)?)),
+            3 => ConditionEnum::And(
+                Box::new(self.condition(depth + 1)?),
+                Box::new(self.condition(depth + 1)?),
+            ),
+            4 => ConditionEnum::Or(
+                Box::new(self.condition(depth + 1)?),
+                Box::new(self.condition(depth + 1)?),
+            ),
+            5 => ConditionEnum::Heard(self.operand()?),
+            code => return Err(self.unknown(code)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bot::Bot;
+
+    const SRC: &str = "proc back left left endProc\n\
+                       set n 3\n\
+                       loop n > 0 and not (botAhead or facingWest)\n\
+                       if wallLeft call back else right endIf\n\
+                       repeat n step endRepeat dec n add n -7\n\
+                       say n if heard 2 or heard n listen m endIf endLoop";
+
+    #[test]
+    fn round_trip() {
+        let mut bot = Bot::new();
+        bot.load_from_string(SRC.to_string()).unwrap();
+        let module = bot.to_module();
+        let bytes = encode(&module).unwrap();
+        assert_eq!(&bytes[..5], b"BOTC\x01");
+        assert_eq!(decode(&bytes), Ok(module.clone()));
+        let text = to_base64(&bytes);
+        assert!(is_base64(&text));
+        assert_eq!(from_base64(&format!("{}\n", text)), Ok(bytes.clone()));
+        let mut loaded = Bot::new();
+        loaded.load_from_bytecode(&bytes).unwrap();
+        assert_eq!(loaded.to_module(), module);
+        //скрипт восстанавливается по командам и компилируется в те же команды
+        assert!(loaded.canonical_source().contains("proc proc1"));
+        let mut restored = Bot::new();
+        restored
+            .load_from_string(loaded.canonical_source().to_string())
+            .unwrap();
+        assert_eq!(restored.to_module(), module);
+    }
+
+    #[test]
+    fn deepest_condition_round_trips() {
+        //цепочка из n and вложена на глубину n
+        let chain = |depth: usize| format!("loop free{} step endLoop", " and free".repeat(depth));
+        let mut bot = Bot::new();
+        bot.load_from_string(chain(MAX_CONDITION_DEPTH)).unwrap();
+        let module = bot.to_module();
+        assert_eq!(decode(&encode(&module).unwrap()), Ok(module));
+        let errors = Bot::new()
+            .load_from_string(chain(MAX_CONDITION_DEPTH + 1))
+            .unwrap_err();
+        assert!(matches!(
+            &errors[..],
+            [crate::parser::CompileError::ConditionTooDeep { token, .. }] if token == "loop"
+        ));
+        let nots = format!(
+            "if {}free step endIf",
+            "not ".repeat(MAX_CONDITION_DEPTH + 1)
+        );
+        assert!(Bot::new().load_from_string(nots).is_err());
+    }
+
+    #[test]
+    fn rejects_broken_bytecode() {
+        let module = Module {
+            commands: vec![CommandEnum::Step, CommandEnum::GoTo(0)],
+            registers: vec![],
+        };
+        let bytes = encode(&module).unwrap();
+        assert_eq!(decode(b"BOTX\x01"), Err(BytecodeError::BadMagic));
+        let mut future = bytes.clone();
+        future[4] = 2;
+        assert_eq!(
+            decode(&future),
+            Err(BytecodeError::UnsupportedVersion { version: 2 })
+        );
+        assert_eq!(
+            decode(&bytes[..bytes.len() - 1]),
+            Err(BytecodeError::UnexpectedEnd {
+                offset: bytes.len() - 1
+            })
+        );
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(
+            decode(&trailing),
+            Err(BytecodeError::TrailingBytes {
+                offset: bytes.len()
+            })
+        );
+        assert_eq!(from_base64("BOTC!"), Err(BytecodeError::BadBase64));
+        let long_name = Module {
+            commands: vec![CommandEnum::Step],
+            registers: vec!["x".to_string(), "y".repeat(70_000)],
+        };
+        assert_eq!(
+            encode(&long_name),
+            Err(BytecodeError::NameTooLong { register: 1 })
+        );
+    }
+
+    #[test]
+    fn checks_targets_and_registers() {
+        let jump_out = Module {
+            commands: vec![CommandEnum::Step, CommandEnum::GoTo(2)],
+            registers: vec![],
+        };
+        assert!(decode(&encode(&jump_out).unwrap()).is_ok());
+        let jump_out = Module {
+            commands: vec![CommandEnum::Step, CommandEnum::Call(3)],
+            registers: vec![],
+        };
+        assert_eq!(
+            decode(&encode(&jump_out).unwrap()),
+            Err(BytecodeError::BadTarget { ptr: 1, target: 3 })
+        );
+        let negative = Module {
+            commands: vec![CommandEnum::CounterNext(-1)],
+            registers: vec![],
+        };
+        assert_eq!(
+            decode(&encode(&negative).unwrap()),
+            Err(BytecodeError::BadTarget { ptr: 0, target: -1 })
+        );
+        let no_register = Module {
+            commands: vec![CommandEnum::GoToNE(
+                ConditionEnum::Compare(
+                    OperandEnum::Register(0),
+                    CompareEnum::Less,
+                    OperandEnum::Register(1),
+                ),
+                0,
+            )],
+            registers: vec!["x".to_string()],
+        };
+        assert_eq!(
+            decode(&encode(&no_register).unwrap()),
+            Err(BytecodeError::BadRegister {
+                ptr: 0,
+                register: 1
+            })
+        );
+    }
+}
@@ -0,0 +1,151 @@
+use crate::ast::{ActionEnum, Block, ConditionEnum, NodeEnum, OperandEnum, Program, Statement};
+use crate::bot::CommandEnum;
+use crate::parser::Span;
+use std::collections::HashMap;
+
+/// Команды бота и то, из какого места скрипта взялась каждая
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Code {
+    pub commands: Vec<CommandEnum>,
+    /// По индексу команды - команда скрипта, из которой она получилась.
+    /// `None` у служебного перехода в конце основной программы
+    pub source_map: Vec<Option<Span>>,
+}
+
+/// Переводит дерево в команды бота.
+/// Основная программа в конце переходит за конец всей программы, что с ботом будет дальше
+/// решает `Bot`. Между ними лежат процедуры, каждая заканчивается return.
+/// Дерево уже проверено парсером, так что ошибок тут не бывает
+pub fn generate(program: &Program) -> Code {
+    let mut codegen = Codegen::default();
+    codegen.block(&program.main);
+    if program.procs.is_empty() {
+        return codegen.code;
+    }
+    let main_end = codegen.emit(CommandEnum::GoTo(0), None);
+    let mut addresses = HashMap::new();
+    for proc in program.procs.iter() {
+        addresses.insert(proc.name.as_str(), codegen.ptr());
+        codegen.block(&proc.body);
+        codegen.emit(CommandEnum::Return, Some(proc.span));
+    }
+    for (idx, name) in codegen.calls.iter() {
+        codegen.code.commands[*idx] = CommandEnum::Call(addresses[name.as_str()]);
+    }
+    codegen.patch(main_end, codegen.ptr());
+    codegen.code
+}
+
+#[derive(Default)]
+struct Codegen {
+    code: Code,
+    /// Где стоят call и кого они зовут, адреса проставляются в конце
+    calls: Vec<(usize, String)>,
+}
+
+impl Codegen {
+    /// Адрес следующей команды
+    fn ptr(&self) -> i32 {
+        self.code.commands.len() as i32
+    }
+
+    /// Добавляет команду и возвращает ее адрес
+    fn emit(&mut self, command: CommandEnum, span: Option<Span>) -> usize {
+        self.code.commands.push(command);
+        self.code.source_map.push(span);
+        self.code.commands.len() - 1
+    }
+
+    /// Проставляет адрес перехода вперед, когда он стал известен
+    fn patch(&mut self, idx: usize, target: i32) {
+        self.code.commands[idx].set_target(target);
+    }
+
+    fn block(&mut self, block: &Block) {
+        for statement in block.statements.iter() {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        let span = Some(statement.span);
+        match &statement.node {
+            NodeEnum::Action(action) => {
+                self.emit(
+                    match action {
+                        ActionEnum::Step => CommandEnum::Step,
+                        ActionEnum::TurnLeft => CommandEnum::TurnLeft,
+                        ActionEnum::TurnRight => CommandEnum::TurnRight,
+                        ActionEnum::TurnRandom => CommandEnum::TurnRandom,
+                    },
+                    span,
+                );
+            }
+            NodeEnum::If(node) => {
+                let jump = self.emit(Self::jump_unless(&node.condition), span);
+                self.block(&node.then_block);
+                match &node.else_block {
+                    Some(else_block) => {
+                        let skip_else = self.emit(CommandEnum::GoTo(0), span);
+                        self.patch(jump, self.ptr());
+                        self.block(else_block);
+                        self.patch(skip_else, self.ptr());
+                    }
+                    None => self.patch(jump, self.ptr()),
+                }
+            }
+            NodeEnum::Loop(node) => {
+                let loop_ptr = self.ptr();
+                let jump = self.emit(Self::jump_unless(&node.condition), span);
+                self.block(&node.body);
+                self.emit(CommandEnum::GoTo(loop_ptr), span);
+                self.patch(jump, self.ptr());
+            }
+            NodeEnum::Repeat(node) => {
+                self.emit(CommandEnum::PushCounter(node.count), span);
+                let next_ptr = self.ptr();
+                let next = self.emit(CommandEnum::CounterNext(0), span);
+                self.block(&node.body);
+                self.emit(CommandEnum::GoTo(next_ptr), span);
+                self.patch(next, self.ptr());
+            }
+            NodeEnum::Call(name) => {
+                let idx = self.emit(CommandEnum::Call(0), span);
+                self.calls.push((idx, name.clone()));
+            }
+            NodeEnum::Stop => {
+                self.emit(CommandEnum::Stop, span);
+            }
+            NodeEnum::Set(register, value) => {
+                self.emit(CommandEnum::Set(*register, *value), span);
+            }
+            NodeEnum::Add(register, value) => {
+                self.emit(CommandEnum::Add(*register, *value), span);
+            }
+            NodeEnum::Sub(register, value) => {
+                self.emit(CommandEnum::Sub(*register, *value), span);
+            }
+            NodeEnum::Inc(register) => {
+                self.emit(CommandEnum::Add(*register, OperandEnum::Value(1)), span);
+            }
+            NodeEnum::Dec(register) => {
+                self.emit(CommandEnum::Sub(*register, OperandEnum::Value(1)), span);
+            }
+            NodeEnum::Say(value) => {
+                self.emit(CommandEnum::Say(*value), span);
+            }
+            NodeEnum::Listen(register) => {
+                self.emit(CommandEnum::Listen(*register), span);
+            }
+        }
+    }
+
+    /// Переход, если условие не выполнено.
+    /// Для `not ...` вместо отрицания переходим по самому условию
+    fn jump_unless(condition: &ConditionEnum) -> CommandEnum {
+        match condition {
+            ConditionEnum::Not(inner) => CommandEnum::GoToIf((**inner).clone(), 0),
+            condition => CommandEnum::GoToNE(condition.clone(), 0),
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use crate::ast::{
+    ActionEnum, Block, ConditionEnum, If, Loop, NodeEnum, OperandEnum, Proc, Program, Repeat,
+    Statement,
+};
+use crate::bot::CommandEnum;
+use crate::codegen;
+use crate::lang::LanguageEnum;
+use crate::parser::{Span, SyntaxEnum};
+
+/// Восстанавливает дерево скрипта по командам бота, например у бота из байткода.
+/// Понимает только то, что выдает `codegen`: циклы, if, repeat и процедуры в конце.
+/// Имен процедур в командах нет, они называются `proc1`, `proc2` и т.д. по порядку.
+/// Если команды так не разбираются (например, после оптимизатора переходы ведут
+/// не туда, куда их ставит `codegen`) или из дерева получаются другие команды - `None`
+pub fn decompile(commands: &[CommandEnum], registers: &[String]) -> Option<Program> {
+    let end = commands.len();
+    //основная программа кончается переходом за конец, после него процедуры с return в конце
+    let main_end = match commands
+        .iter()
+        .position(|command| *command == CommandEnum::Return)
+    {
+        Some(first_return) => commands[..first_return]
+            .iter()
+            .rposition(|command| *command == CommandEnum::GoTo(end as i32))?,
+        None => end,
+    };
+    let mut proc_starts = vec![];
+    let mut start = main_end + 1;
+    while start < end {
+        let length = commands[start..]
+            .iter()
+            .position(|command| *command == CommandEnum::Return)?;
+        proc_starts.push((start, start + length));
+        start += length + 1;
+    }
+    let decompiler = Decompiler {
+        commands,
+        proc_starts: proc_starts.iter().map(|(start, _)| *start).collect(),
+    };
+    let main = decompiler.block(0, main_end)?;
+    let mut procs = vec![];
+    for (idx, (start, end)) in proc_starts.iter().enumerate() {
+        procs.push(Proc {
+            name: proc_name(idx),
+            span: Span::default(),
+            body: decompiler.block(*start, *end)?,
+        });
+    }
+    let program = Program {
+        main,
+        procs,
+        registers: registers.to_vec(),
+        language: LanguageEnum::English,
+        syntax: SyntaxEnum::Keywords,
+    };
+    //разбор по форме переходов может ошибиться, так что дерево проверяется обратной компиляцией
+    if codegen::generate(&program).commands != commands {
+        return None;
+    }
+    Some(program)
+}
+
+fn proc_name(idx: usize) -> String {
+    format!("proc{}", idx + 1)
+}
+
+struct Decompiler<'a> {
+    commands: &'a [CommandEnum],
+    /// Адреса начала процедур, по ним call находит имя
+    proc_starts: Vec<usize>,
+}
+
+impl Decompiler<'_> {
+    /// Команды с `start` до `end` (не включая) как один блок.
+    /// Все переходы изнутри должны оставаться внутри, иначе это не блок
+    fn block(&self, start: usize, end: usize) -> Option<Block> {
+        let mut statements = vec![];
+        let mut ptr = start;
+        while ptr < end {
+            let (node, next) = self.statement(ptr, end)?;
+            statements.push(Statement {
+                node,
+                span: Span::default(),
+            });
+            ptr = next;
+        }
+        Some(Block {
+            statements,
+            end: None,
+        })
+    }
+
+    /// Команда скрипта, которая начинается с `ptr`, и адрес следующей за ней
+    fn statement(&self, ptr: usize, end: usize) -> Option<(NodeEnum, usize)> {
+        let node = match &self.commands[ptr] {
+            CommandEnum::Step => NodeEnum::Action(ActionEnum::Step),
+            CommandEnum::TurnLeft => NodeEnum::Action(ActionEnum::TurnLeft),
+            CommandEnum::TurnRight => NodeEnum::Action(ActionEnum::TurnRight),
+            CommandEnum::TurnRandom => NodeEnum::Action(ActionEnum::TurnRandom),
+            CommandEnum::Stop => NodeEnum::Stop,
+            CommandEnum::Set(register, value) => NodeEnum::Set(*register, *value),
+            CommandEnum::Add(register, OperandEnum::Value(1)) => NodeEnum::Inc(*register),
+            CommandEnum::Sub(register, OperandEnum::Value(1)) => NodeEnum::Dec(*register),
+            CommandEnum::Add(register, value) => NodeEnum::Add(*register, *value),
+            CommandEnum::Sub(register, value) => NodeEnum::Sub(*register, *value),
+            CommandEnum::Say(value) => NodeEnum::Say(*value),
+            CommandEnum::Listen(register) => NodeEnum::Listen(*register),
+            CommandEnum::Call(target) => {
+                let idx = self
+                    .proc_starts
+                    .iter()
+                    .position(|start| *start as i32 == *target)?;
+                NodeEnum::Call(proc_name(idx))
+            }
+            CommandEnum::GoToNE(condition, target) => {
+                return self.branch(ptr, end, condition.clone(), *target);
+            }
+            CommandEnum::GoToIf(condition, target) => {
+                let condition = ConditionEnum::Not(Box::new(condition.clone()));
+                return self.branch(ptr, end, condition, *target);
+            }
+            CommandEnum::PushCounter(count) => return self.repeat(ptr, end, *count),
+            CommandEnum::GoTo(_) | CommandEnum::CounterNext(_) | CommandEnum::Return => {
+                return None
+            }
+        };
+        Some((node, ptr + 1))
+    }
+
+    /// loop или if: переход по условию в `ptr` ведет на `target`, сразу за блок.
+    /// Перед `target` у loop - переход обратно на условие, у if с else - переход за else
+    fn branch(
+        &self,
+        ptr: usize,
+        end: usize,
+        condition: ConditionEnum,
+        target: i32,
+    ) -> Option<(NodeEnum, usize)> {
+        let target = usize::try_from(target).ok()?;
+        if target <= ptr || target > end {
+            return None;
+        }
+        if let CommandEnum::GoTo(back) = self.commands[target - 1] {
+            if back == ptr as i32 && target - 1 > ptr {
+                let body = self.block(ptr + 1, target - 1)?;
+                return Some((NodeEnum::Loop(Loop { condition, body }), target));
+            }
+            //такой же переход в конце then бывает и у вложенного if с пустым else,
+            //так что если за else блок не складывается - это if без else
+            if back >= target as i32 && back <= end as i32 && target - 1 > ptr {
+                let after = back as usize;
+                let then_block = self.block(ptr + 1, target - 1);
+                let else_block = self.block(target, after);
+                if let (Some(then_block), Some(else_block)) = (then_block, else_block) {
+                    let node = If {
+                        condition,
+                        then_block,
+                        else_block: Some(else_block),
+                    };
+                    return Some((NodeEnum::If(node), after));
+                }
+            }
+        }
+        let node = If {
+            condition,
+            then_block: self.block(ptr + 1, target)?,
+            else_block: None,
+        };
+        Some((NodeEnum::If(node), target))
+    }
+
+    /// repeat: счетчик, проверка счетчика с переходом за блок, тело и переход на проверку
+    fn repeat(&self, ptr: usize, end: usize, count: OperandEnum) -> Option<(NodeEnum, usize)> {
+        let next = ptr + 1;
+        let CommandEnum::CounterNext(target) = *self.commands.get(next)? else {
+            return None;
+        };
+        let target = usize::try_from(target).ok()?;
+        if target <= next + 1
+            || target > end
+            || self.commands[target - 1] != CommandEnum::GoTo(next as i32)
+        {
+            return None;
+        }
+        let body = self.block(next + 1, target - 1)?;
+        Some((NodeEnum::Repeat(Repeat { count, body }), target))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+    use crate::printer::print_program;
+
+    #[test]
+    fn restores_structured_program() {
+        let src = "loop n < 3 and not botAhead\n\
+                   if wallLeft call turn else step endIf\n\
+                   repeat n if not free stop endIf say n endRepeat\n\
+                   add n 2 dec n listen x\n\
+                   endLoop\n\
+                   proc turn leftOrRight endProc\n\
+                   proc empty endProc";
+        let program = parse(src).unwrap();
+        let code = codegen::generate(&program);
+        let restored = decompile(&code.commands, &program.registers).unwrap();
+        assert_eq!(
+            print_program(&restored),
+            print_program(&program)
+                .replace("turn", "proc1")
+                .replace("empty", "proc2")
+        );
+
+        //у переходов, которые ведут не туда, куда их ставит codegen, скрипта нет
+        let threaded = vec![
+            CommandEnum::GoTo(2),
+            CommandEnum::GoTo(0),
+            CommandEnum::Step,
+        ];
+        assert_eq!(decompile(&threaded, &[]), None);
+        let nested = parse("if free if wallLeft step else endIf endIf").unwrap();
+        let code = codegen::generate(&nested);
+        assert!(decompile(&code.commands, &[]).is_some());
+        assert_eq!(decompile(&[], &[]), Some(parse("").unwrap()));
+    }
+}
@@ -0,0 +1,134 @@
+use crate::ast::ActionEnum;
+use crate::bot::CommandEnum;
+use crate::parser::Span;
+use crate::printer::{print_action, print_condition, print_operand};
+use std::fmt;
+
+/// Одна команда бота в читаемом виде
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instruction {
+    pub index: usize,
+    /// Команда без адреса перехода, например `ifNot free` или `set x 3`
+    pub text: String,
+    /// Куда команда может перейти
+    pub target: Option<i32>,
+    /// Строка скрипта, из которой получилась команда
+    pub line: Option<usize>,
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut text = self.text.clone();
+        if let Some(target) = self.target {
+            text.push_str(&format!(" -> {}", target));
+        }
+        match self.line {
+            Some(line) => write!(f, "{:>4}  {:<32} # строка {}", self.index, text, line),
+            None => write!(f, "{:>4}  {}", self.index, text),
+        }
+    }
+}
+
+/// Расписывает команды по одной, `registers` - имена переменных для операндов
+pub fn disassemble(
+    commands: &[CommandEnum],
+    source_map: &[Option<Span>],
+    registers: &[String],
+) -> Vec<Instruction> {
+    commands
+        .iter()
+        .enumerate()
+        .map(|(index, command)| Instruction {
+            index,
+            text: command_text(command, registers),
+            target: command.target(),
+            line: source_map
+                .get(index)
+                .copied()
+                .flatten()
+                .map(|span| span.line),
+        })
+        .collect()
+}
+
+/// Весь листинг одной строкой, команда на строку
+pub fn listing(instructions: &[Instruction]) -> String {
+    instructions
+        .iter()
+        .map(|instruction| format!("{}\n", instruction))
+        .collect()
+}
+
+fn command_text(command: &CommandEnum, registers: &[String]) -> String {
+    match command {
+        CommandEnum::Step => print_action(ActionEnum::Step).to_string(),
+        CommandEnum::TurnLeft => print_action(ActionEnum::TurnLeft).to_string(),
+        CommandEnum::TurnRight => print_action(ActionEnum::TurnRight).to_string(),
+        CommandEnum::TurnRandom => print_action(ActionEnum::TurnRandom).to_string(),
+        CommandEnum::GoTo(_) => "goto".to_string(),
+        CommandEnum::GoToNE(condition, _) => {
+            format!("ifNot {}", print_condition(condition, registers))
+        }
+        CommandEnum::GoToIf(condition, _) => {
+            format!("if {}", print_condition(condition, registers))
+        }
+        CommandEnum::PushCounter(count) => {
+            format!("pushCounter {}", print_operand(*count, registers))
+        }
+        CommandEnum::CounterNext(_) => "counterNext".to_string(),
+        CommandEnum::Call(_) => "call".to_string(),
+        CommandEnum::Return => "return".to_string(),
+        CommandEnum::Stop => "stop".to_string(),
+        CommandEnum::Set(register, value) => {
+            format!(
+                "set {} {}",
+                registers[*register],
+                print_operand(*value, registers)
+            )
+        }
+        CommandEnum::Add(register, value) => {
+            format!(
+                "add {} {}",
+                registers[*register],
+                print_operand(*value, registers)
+            )
+        }
+        CommandEnum::Sub(register, value) => {
+            format!(
+                "sub {} {}",
+                registers[*register],
+                print_operand(*value, registers)
+            )
+        }
+        CommandEnum::Say(value) => format!("say {}", print_operand(*value, registers)),
+        CommandEnum::Listen(register) => format!("listen {}", registers[*register]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bot::Bot;
+
+    #[test]
+    fn lists_jumps_and_lines() {
+        let mut bot = Bot::new();
+        bot.load_from_string(
+            "set x 2\nloop x > 0\n  if not wallLeft left endIf\n  dec x\nendLoop".to_string(),
+        )
+        .unwrap();
+        let instructions = bot.disassemble();
+        assert_eq!(instructions[1].text, "ifNot x > 0");
+        assert_eq!(instructions[1].target, Some(6));
+        assert_eq!(instructions[1].line, Some(2));
+        assert_eq!(instructions[2].text, "if wallLeft");
+        assert_eq!(instructions[5].target, Some(1));
+        assert_eq!(
+            instructions[5].to_string(),
+            "   5  goto -> 1                        # строка 2"
+        );
+        assert_eq!(
+            instructions[4].to_string().trim(),
+            "4  sub x 1                          # строка 4"
+        );
+    }
+}
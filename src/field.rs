@@ -1,7 +1,22 @@
-use std::collections::HashSet;
-use crate::bot::{Bot, BotActionEnum};
-use rand::Rng;
+use crate::bot::{Bot, BotActionEnum, BreakpointEnum, ProgramEndEnum, RuntimeError, Sensors};
+use crate::bytecode::BytecodeError;
+use crate::parser::{CompileError, SyntaxEnum};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
 use random_color::RandomColor;
+use std::collections::HashSet;
+
+/// Почему бот не попал на поле
+#[derive(Debug, Clone, PartialEq)]
+pub enum AddBotError {
+    /// Скрипт не компилируется
+    Compile(Vec<CompileError>),
+    /// Байткод не загружается
+    Bytecode(BytecodeError),
+    /// На поле не осталось свободных клеток
+    FieldFull,
+}
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum DirectionEnum {
@@ -11,27 +26,150 @@ pub enum DirectionEnum {
     Left,
 }
 
+impl DirectionEnum {
+    fn turn_left(self) -> Self {
+        match self {
+            DirectionEnum::Up => DirectionEnum::Left,
+            DirectionEnum::Left => DirectionEnum::Down,
+            DirectionEnum::Down => DirectionEnum::Right,
+            DirectionEnum::Right => DirectionEnum::Up,
+        }
+    }
+
+    fn turn_back(self) -> Self {
+        self.turn_left().turn_left()
+    }
+
+    fn turn_right(self) -> Self {
+        self.turn_back().turn_left()
+    }
+
+    /// Куда сдвинется клетка при шаге в этом направлении
+    fn offset(self) -> (i32, i32) {
+        match self {
+            DirectionEnum::Up => (0, -1),
+            DirectionEnum::Down => (0, 1),
+            DirectionEnum::Right => (1, 0),
+            DirectionEnum::Left => (-1, 0),
+        }
+    }
+}
+
+/// Как далеко по умолчанию слышно `say`, в шагах по клеткам без диагоналей
+pub const DEFAULT_SAY_RANGE: i32 = 3;
+
+/// Сколько раз бот пробует встать на случайную клетку, прежде чем искать среди всех свободных
+const RANDOM_CELL_TRIES: usize = 32;
+
+/// Сколько программы бот выполняет за ход
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExecutionModeEnum {
+    /// Одна команда за ход, переходы и переменные тоже тратят ход
+    PerInstruction,
+    /// Команды до первого действия, но не больше `budget`
+    PerTick { budget: usize },
+}
+
 pub struct Field {
     pub width: i32,
     pub height: i32,
     walls: HashSet<(i32, i32)>,
     bots: Vec<BotWrapper>,
+    /// Оптимизировать ли программы новых ботов, для отладки удобнее без этого
+    pub optimize: bool,
+    pub mode: ExecutionModeEnum,
+    /// Что делают новые боты, дойдя до конца программы
+    pub program_end: ProgramEndEnum,
+    /// Как далеко слышно `say`, стены звуку не мешают
+    pub say_range: i32,
+    /// С одним и тем же сидом стены, боты и их случайные повороты всегда одинаковые
+    seed: u64,
+    rng: StdRng,
+}
+
+impl Default for Field {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Field {
     pub fn new() -> Self {
+        Self::with_seed(rand::thread_rng().gen())
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
         Field {
             width: 10,
             height: 10,
             bots: vec![],
-            walls: HashSet::new()
+            walls: HashSet::new(),
+            //оптимизатор меняет, сколько ходов бот тратит на команды, так что только по галке
+            optimize: false,
+            //по команде за ход: скрипт вроде `loop not free endLoop` в PerTick завис бы
+            mode: ExecutionModeEnum::PerInstruction,
+            program_end: ProgramEndEnum::Loop,
+            say_range: DEFAULT_SAY_RANGE,
+            seed,
+            rng: StdRng::seed_from_u64(seed),
         }
     }
 
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Все боты остановились или упали, дальше на поле ничего не поменяется
+    pub fn all_stopped(&self) -> bool {
+        !self.bots.is_empty()
+            && self
+                .bots
+                .iter()
+                .all(|bot_wrapper| bot_wrapper.bot.is_stopped())
+    }
+
     pub fn get_bots_count(&self) -> usize {
         self.bots.len()
     }
 
+    pub fn get_bot(&self, bot_idx: usize) -> Option<&Bot> {
+        self.bots.get(bot_idx).map(|bot_wrapper| &bot_wrapper.bot)
+    }
+
+    /// Цвет, которым бот нарисован на поле
+    pub fn get_bot_color(&self, bot_idx: usize) -> Option<&str> {
+        self.bots
+            .get(bot_idx)
+            .map(|bot_wrapper| bot_wrapper.color.as_str())
+    }
+
+    /// Где стоит бот и куда смотрит
+    pub fn get_bot_position(&self, bot_idx: usize) -> Option<(i32, i32, DirectionEnum)> {
+        self.bots
+            .get(bot_idx)
+            .map(|bot_wrapper| (bot_wrapper.x, bot_wrapper.y, bot_wrapper.direction))
+    }
+
+    /// Номер бота в клетке, если их там несколько - первого, как в `get_cell_state`
+    pub fn get_bot_at(&self, x: i32, y: i32) -> Option<usize> {
+        self.bots
+            .iter()
+            .position(|bot_wrapper| bot_wrapper.x == x && bot_wrapper.y == y)
+    }
+
+    pub fn toggle_breakpoint(&mut self, bot_idx: usize, breakpoint: BreakpointEnum) {
+        if let Some(bot_wrapper) = self.bots.get_mut(bot_idx) {
+            bot_wrapper.bot.toggle_breakpoint(breakpoint);
+        }
+    }
+
+    /// Первый бот, который стоит на точке останова
+    pub fn bot_at_breakpoint(&self) -> Option<usize> {
+        self.bots
+            .iter()
+            .position(|bot_wrapper| bot_wrapper.bot.at_breakpoint())
+    }
+
     pub fn get_cell_state(&self, x: i32, y: i32) -> Option<FieldCellState> {
         let bots: Vec<&BotWrapper> = self
             .bots
@@ -47,11 +185,23 @@ impl Field {
         }
     }
 
-    pub fn do_bot_step(&mut self, bot_idx: usize) -> Result<(), String> {
-        let bot = &self.bots[bot_idx];
-        let bot_can_step = self.calc_can_step(bot);
+    pub fn do_bot_step(&mut self, bot_idx: usize) -> Result<(), RuntimeError> {
+        self.run_bot(bot_idx, self.mode)
+    }
+
+    /// Ровно одна команда одного бота, какой бы ни был `mode`. Для отладчика
+    pub fn do_bot_instruction(&mut self, bot_idx: usize) -> Result<(), RuntimeError> {
+        self.run_bot(bot_idx, ExecutionModeEnum::PerInstruction)
+    }
+
+    fn run_bot(&mut self, bot_idx: usize, mode: ExecutionModeEnum) -> Result<(), RuntimeError> {
+        let sensors = self.calc_sensors(&self.bots[bot_idx]);
         let bot_wrapper = &mut self.bots[bot_idx];
-        let step_result = bot_wrapper.bot.do_step(bot_can_step).ok_or("End")?;
+        //бот, который за ход не дошел до действия, завис - это уходит наружу как ошибка
+        let step_result = match mode {
+            ExecutionModeEnum::PerInstruction => bot_wrapper.bot.do_step(&sensors)?,
+            ExecutionModeEnum::PerTick { budget } => bot_wrapper.bot.do_tick(&sensors, budget)?,
+        };
 
         match step_result {
             BotActionEnum::Step => bot_wrapper.calc_next_position(self.width, self.height),
@@ -61,45 +211,122 @@ impl Field {
         Ok(())
     }
 
-    fn calc_can_step(&self, bot_wrapper: &BotWrapper) -> bool {
-        let next_coord = match bot_wrapper.direction {
-            DirectionEnum::Up => (bot_wrapper.x, bot_wrapper.y - 1),
-            DirectionEnum::Down => (bot_wrapper.x, bot_wrapper.y + 1),
-            DirectionEnum::Right => (bot_wrapper.x + 1, bot_wrapper.y),
-            DirectionEnum::Left => (bot_wrapper.x - 1, bot_wrapper.y),
+    fn calc_sensors(&self, bot_wrapper: &BotWrapper) -> Sensors {
+        let direction = bot_wrapper.direction;
+        let neighbour = |direction: DirectionEnum| {
+            let (dx, dy) = direction.offset();
+            (bot_wrapper.x + dx, bot_wrapper.y + dy)
         };
-        if next_coord.0 < 0
-            || next_coord.0 > self.width - 1
-            || next_coord.1 < 0
-            || next_coord.1 > self.height - 1
-        {
-            return false;
+        let ahead = neighbour(direction);
+        Sensors {
+            free_ahead: self.is_free(ahead),
+            free_behind: self.is_free(neighbour(direction.turn_back())),
+            wall_left: self.is_wall(neighbour(direction.turn_left())),
+            wall_right: self.is_wall(neighbour(direction.turn_right())),
+            bot_ahead: matches!(
+                self.get_cell_state(ahead.0, ahead.1),
+                Some(FieldCellState::Bot(_))
+            ),
+            at_edge: !self.is_inside(ahead),
+            direction,
         }
-        self.get_cell_state(next_coord.0, next_coord.1).is_none()
     }
 
-    pub fn step(&mut self) {
-        let count = self.bots.len();
-        for idx in 0..count {
-            self.do_bot_step(idx).unwrap();
+    fn is_inside(&self, (x, y): (i32, i32)) -> bool {
+        x >= 0 && x < self.width && y >= 0 && y < self.height
+    }
+
+    fn is_free(&self, coord: (i32, i32)) -> bool {
+        self.is_inside(coord) && self.get_cell_state(coord.0, coord.1).is_none()
+    }
+
+    /// Край поля для бота тоже стена
+    fn is_wall(&self, coord: (i32, i32)) -> bool {
+        !self.is_inside(coord) || self.walls.contains(&coord)
+    }
+
+    /// Ход всех ботов. Бот с ошибкой пропускает ход, остальные ходят как обычно,
+    /// наружу уходит первая ошибка. После хода сказанное разносится по ботам
+    pub fn step(&mut self) -> Result<(), RuntimeError> {
+        let mut result = Ok(());
+        for idx in 0..self.bots.len() {
+            result = result.and(self.do_bot_step(idx));
         }
+        self.deliver_messages();
+        result
     }
 
-    pub fn add_bot(&mut self, src: String) {
+    /// Каждый бот слышит то, что сказали другие боты не дальше `say_range`,
+    /// считая от того места, где они стоят после хода. Сам себя бот не слышит
+    fn deliver_messages(&mut self) {
+        let said: Vec<(i32, i32, Vec<i32>)> = self
+            .bots
+            .iter_mut()
+            .map(|bot_wrapper| (bot_wrapper.x, bot_wrapper.y, bot_wrapper.bot.take_said()))
+            .collect();
+        for (idx, bot_wrapper) in self.bots.iter_mut().enumerate() {
+            let heard = said
+                .iter()
+                .enumerate()
+                .filter(|(other, (x, y, _))| {
+                    *other != idx
+                        && (x - bot_wrapper.x).abs() + (y - bot_wrapper.y).abs() <= self.say_range
+                })
+                .flat_map(|(_, (_, _, messages))| messages.iter().copied())
+                .collect();
+            bot_wrapper.bot.hear(heard);
+        }
+    }
+
+    pub fn add_bot(&mut self, src: String) -> Result<(), AddBotError> {
+        self.add_bot_with_syntax(src, SyntaxEnum::Keywords)
+    }
+
+    /// Бот из скрипта в синтаксисе `syntax`, прагма `#syntax` в скрипте важнее
+    pub fn add_bot_with_syntax(
+        &mut self,
+        src: String,
+        syntax: SyntaxEnum,
+    ) -> Result<(), AddBotError> {
+        let mut bot = Bot::new();
+        bot.set_optimize(self.optimize);
+        bot.set_syntax(syntax);
+        bot.load_from_string(src).map_err(AddBotError::Compile)?;
+        self.place_bot(bot)
+    }
+
+    /// Бот из байткода, без компиляции
+    pub fn add_compiled_bot(&mut self, bytes: &[u8]) -> Result<(), AddBotError> {
         let mut bot = Bot::new();
-        bot.load_from_string(src).unwrap();
-        let (x, y) = self.get_random_empty_cell();
-        let direction = Self::get_random_direction();
-        self.bots.push(BotWrapper::new(bot, x, y, direction));
+        bot.load_from_bytecode(bytes)
+            .map_err(AddBotError::Bytecode)?;
+        self.place_bot(bot)
+    }
+
+    fn place_bot(&mut self, mut bot: Bot) -> Result<(), AddBotError> {
+        //поток бота зависит только от сида поля и номера бота,
+        //так что стены и другие боты на его случайности не влияют
+        let bot_seed = (self.bots.len() as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        bot.set_seed(self.seed ^ bot_seed);
+        bot.set_program_end(self.program_end);
+        let (x, y) = self.get_random_empty_cell().ok_or(AddBotError::FieldFull)?;
+        let direction = self.get_random_direction();
+        let color_seed = self.rng.gen();
+        self.bots
+            .push(BotWrapper::new(bot, x, y, direction, color_seed));
+        Ok(())
     }
 
     pub fn add_random_wall(&mut self, wall_percent: i32) {
-        let mut wall_count = self.width * self.height / 100 * wall_percent;
-        while wall_count > 0 {
-            let wall = self.get_random_empty_cell();
-            self.walls.insert(wall);
-            wall_count -= 1;
-        }
+        //сначала умножение: на поле 5x5 при делении первым стен не было бы совсем.
+        //В i64, чтобы большое поле не переполнило i32
+        let wall_count =
+            i64::from(self.width) * i64::from(self.height) * i64::from(wall_percent) / 100;
+        let wall_count = usize::try_from(wall_count).unwrap_or(0);
+        //свободные клетки собираются один раз, стены - первые из них после перемешивания
+        let mut cells = self.free_cells();
+        let (walls, _) = cells.partial_shuffle(&mut self.rng, wall_count);
+        self.walls.extend(walls.iter().copied());
     }
 
     pub fn turn_wall(&mut self, x: i32, y: i32) {
@@ -110,20 +337,38 @@ impl Field {
         };
     }
 
-    fn get_random_empty_cell(&self) -> (i32, i32) {
-        let mut rnd = rand::thread_rng();
-        loop {
-            let x = rnd.gen_range(0..(self.width));
-            let y = rnd.gen_range(0..(self.height));
+    /// Случайная свободная клетка, `None` - если свободных нет.
+    /// Обычно свободная находится с нескольких попыток, а список всех свободных
+    /// собирается, только если поле почти заполнено
+    fn get_random_empty_cell(&mut self) -> Option<(i32, i32)> {
+        if self.width < 1 || self.height < 1 {
+            return None;
+        }
+        for _ in 0..RANDOM_CELL_TRIES {
+            let x = self.rng.gen_range(0..(self.width));
+            let y = self.rng.gen_range(0..(self.height));
             if self.get_cell_state(x, y).is_none() {
-                return (x, y);
+                return Some((x, y));
             }
         }
+        self.free_cells().choose(&mut self.rng).copied()
+    }
+
+    /// Все клетки без стен и ботов, по строкам
+    fn free_cells(&self) -> Vec<(i32, i32)> {
+        let bots: HashSet<(i32, i32)> = self
+            .bots
+            .iter()
+            .map(|bot_wrapper| (bot_wrapper.x, bot_wrapper.y))
+            .collect();
+        (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| (x, y)))
+            .filter(|cell| !bots.contains(cell) && !self.walls.contains(cell))
+            .collect()
     }
 
-    fn get_random_direction() -> DirectionEnum {
-        let mut rnd = rand::thread_rng();
-        match rnd.gen_range(0..4) {
+    fn get_random_direction(&mut self) -> DirectionEnum {
+        match self.rng.gen_range(0..4) {
             0 => DirectionEnum::Up,
             1 => DirectionEnum::Left,
             2 => DirectionEnum::Down,
@@ -138,17 +383,17 @@ pub struct BotWrapper {
     x: i32,
     y: i32,
     direction: DirectionEnum,
-    color: String
+    color: String,
 }
 
 impl BotWrapper {
-    pub fn new(bot: Bot, x: i32, y: i32, direction: DirectionEnum) -> Self {
+    pub fn new(bot: Bot, x: i32, y: i32, direction: DirectionEnum, color_seed: u64) -> Self {
         BotWrapper {
             bot,
             x,
             y,
             direction,
-            color:  RandomColor::new().to_rgb_string()
+            color: RandomColor::new().seed(color_seed).to_rgb_string(),
         }
     }
 
@@ -197,3 +442,224 @@ pub enum FieldCellState {
     Wall,
     Bot(String),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field_with_bot(x: i32, y: i32, direction: DirectionEnum) -> Field {
+        let mut fld = Field::new();
+        fld.width = 3;
+        fld.height = 3;
+        fld.bots
+            .push(BotWrapper::new(Bot::new(), x, y, direction, 0));
+        fld
+    }
+
+    #[test]
+    fn sensors_in_corner() {
+        let mut fld = field_with_bot(0, 0, DirectionEnum::Right);
+        fld.turn_wall(0, 1);
+        let sensors = fld.calc_sensors(&fld.bots[0]);
+        assert_eq!(
+            sensors,
+            Sensors {
+                free_ahead: true,
+                free_behind: false,
+                wall_left: true,
+                wall_right: true,
+                bot_ahead: false,
+                at_edge: false,
+                direction: DirectionEnum::Right,
+            }
+        );
+    }
+
+    #[test]
+    fn step_reports_livelock() {
+        let mut fld = field_with_bot(0, 0, DirectionEnum::Right);
+        fld.bots[0]
+            .bot
+            .load_from_string("loop step endLoop inc x".to_string())
+            .unwrap();
+        fld.mode = ExecutionModeEnum::PerTick { budget: 50 };
+        assert_eq!(fld.step(), Ok(()));
+        assert_eq!((fld.bots[0].x, fld.bots[0].y), (1, 0));
+        fld.step().unwrap();
+        //уперся в край: цикл пропускается, дальше только inc
+        assert!(matches!(
+            fld.step(),
+            Err(RuntimeError::Livelock { budget: 50, .. })
+        ));
+        fld.mode = ExecutionModeEnum::PerInstruction;
+        assert_eq!(fld.step(), Ok(()));
+    }
+
+    #[test]
+    fn waiting_bot_is_not_livelock_by_default() {
+        let mut fld = Field::with_seed(4);
+        fld.width = 1;
+        fld.height = 1;
+        assert_eq!(fld.mode, ExecutionModeEnum::PerInstruction);
+        fld.add_bot("loop not free endLoop".to_string()).unwrap();
+        for _ in 0..100 {
+            assert_eq!(fld.step(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn same_seed_same_run() {
+        let run = |seed: u64| {
+            let mut fld = Field::with_seed(seed);
+            fld.add_random_wall(30);
+            for _ in 0..3 {
+                fld.add_bot("loop step endLoop leftOrRight".to_string())
+                    .unwrap();
+            }
+            let mut positions = vec![];
+            for _ in 0..50 {
+                fld.step().unwrap();
+                positions.extend(
+                    fld.bots
+                        .iter()
+                        .map(|bot| (bot.x, bot.y, bot.direction, bot.color.clone())),
+                );
+            }
+            (fld.walls.iter().copied().collect::<Vec<_>>(), positions)
+        };
+        let mut first = run(1234);
+        let mut second = run(1234);
+        first.0.sort();
+        second.0.sort();
+        assert_eq!(first, second);
+        assert_ne!(run(1234).1, run(4321).1);
+    }
+
+    #[test]
+    fn bot_stays_on_small_field() {
+        let mut fld = Field::with_seed(3);
+        fld.width = 3;
+        fld.height = 3;
+        fld.add_bot("loop\nloop\nstep\nendLoop\nright\nendLoop\nright".to_string())
+            .unwrap();
+        for _ in 0..100 {
+            fld.step().unwrap();
+            let (x, y, _) = fld.get_bot_position(0).unwrap();
+            assert!((0..3).contains(&x) && (0..3).contains(&y));
+        }
+        assert!(!fld.all_stopped());
+    }
+
+    #[test]
+    fn full_field_rejects_bot() {
+        let mut fld = Field::with_seed(9);
+        fld.width = 5;
+        fld.height = 5;
+        fld.add_random_wall(50);
+        assert_eq!(fld.walls.len(), 12);
+        fld.add_random_wall(99);
+        assert_eq!(fld.walls.len(), 25);
+        assert_eq!(fld.add_bot("step".to_string()), Err(AddBotError::FieldFull));
+
+        let mut fld = Field::with_seed(9);
+        fld.width = 1;
+        fld.height = 1;
+        fld.add_bot("step".to_string()).unwrap();
+        assert_eq!(fld.add_bot("step".to_string()), Err(AddBotError::FieldFull));
+        assert_eq!(fld.get_bots_count(), 1);
+    }
+
+    #[test]
+    fn messages_reach_bots_in_range() {
+        let mut fld = Field::with_seed(5);
+        fld.width = 10;
+        fld.height = 1;
+        fld.say_range = 2;
+        fld.mode = ExecutionModeEnum::PerTick { budget: 10 };
+        for x in [0, 2, 5] {
+            let mut bot = Bot::new();
+            bot.load_from_string(format!("say {} left", x)).unwrap();
+            fld.bots
+                .push(BotWrapper::new(bot, x, 0, DirectionEnum::Up, 0));
+        }
+        fld.step().unwrap();
+        let inbox = |fld: &Field, idx: usize| fld.get_bot(idx).unwrap().view().inbox.to_vec();
+        assert_eq!(inbox(&fld, 0), vec![2]);
+        assert_eq!(inbox(&fld, 1), vec![0]);
+        assert_eq!(inbox(&fld, 2), Vec::<i32>::new());
+        //непрочитанное копится, пока его не заберет listen
+        fld.say_range = 5;
+        fld.step().unwrap();
+        assert_eq!(inbox(&fld, 0), vec![2, 2, 5]);
+        assert_eq!(inbox(&fld, 2), vec![0, 2]);
+    }
+
+    #[test]
+    fn messages_wait_for_listen() {
+        let mut fld = Field::with_seed(5);
+        fld.width = 10;
+        fld.height = 1;
+        assert_eq!(fld.mode, ExecutionModeEnum::PerInstruction);
+        let scripts = ["say 4 say 9 stop", "left left left listen x listen y stop"];
+        for (x, script) in scripts.iter().enumerate() {
+            let mut bot = Bot::new();
+            bot.load_from_string(script.to_string()).unwrap();
+            fld.bots
+                .push(BotWrapper::new(bot, x as i32, 0, DirectionEnum::Up, 0));
+        }
+        //сказано на первых двух ходах, а слушает второй бот только на четвертом и пятом
+        for _ in 0..5 {
+            fld.step().unwrap();
+        }
+        let registers = fld.get_bot(1).unwrap().view().registers;
+        assert_eq!(registers, vec![("x", 4), ("y", 9)]);
+        assert!(fld.get_bot(1).unwrap().view().inbox.is_empty());
+    }
+
+    #[test]
+    fn all_stopped() {
+        let mut fld = Field::with_seed(1);
+        assert!(!fld.all_stopped());
+        fld.program_end = ProgramEndEnum::Halt;
+        fld.add_bot("left".to_string()).unwrap();
+        fld.add_bot("right right".to_string()).unwrap();
+        fld.step().unwrap();
+        assert!(!fld.all_stopped());
+        fld.step().unwrap();
+        fld.step().unwrap();
+        assert!(fld.all_stopped());
+    }
+
+    #[test]
+    fn breakpoint_pauses_one_bot() {
+        let mut fld = Field::with_seed(7);
+        fld.width = 5;
+        fld.height = 5;
+        //оптимизатор по умолчанию выключен, так что left и right друг друга не съедят
+        assert!(!fld.optimize);
+        fld.add_bot("left\nright".to_string()).unwrap();
+        fld.add_bot("left\nright".to_string()).unwrap();
+        fld.toggle_breakpoint(1, BreakpointEnum::Line(2));
+        assert_eq!(fld.bot_at_breakpoint(), None);
+        let (_, _, direction) = fld.get_bot_position(1).unwrap();
+        fld.step().unwrap();
+        assert_eq!(fld.bot_at_breakpoint(), Some(1));
+        assert_eq!(fld.get_bot_position(1).unwrap().2, direction.turn_left());
+        //в отладчике бот идет по одной команде и уходит с точки останова
+        fld.do_bot_instruction(1).unwrap();
+        assert_eq!(fld.bot_at_breakpoint(), None);
+        assert_eq!(fld.get_bot_position(1).unwrap().2, direction);
+        assert_eq!(fld.get_bot(0).unwrap().view().command_ptr, 1);
+    }
+
+    #[test]
+    fn sensors_see_bot_and_edge() {
+        let mut fld = field_with_bot(1, 1, DirectionEnum::Up);
+        fld.bots
+            .push(BotWrapper::new(Bot::new(), 1, 0, DirectionEnum::Up, 0));
+        let first = fld.calc_sensors(&fld.bots[0]);
+        assert!(first.bot_ahead && !first.free_ahead && !first.at_edge);
+        let second = fld.calc_sensors(&fld.bots[1]);
+        assert!(second.at_edge && !second.bot_ahead && !second.free_behind);
+    }
+}
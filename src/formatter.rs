@@ -0,0 +1,124 @@
+use crate::parser::{self, CompileError, SyntaxEnum};
+use crate::printer::{self, PrintedLine, Style};
+
+/// Приводит скрипт к одному виду: ключевые слова пишутся как в справке (`ENDLOOP` - `endLoop`),
+/// одна команда на строку, отступ в 4 пробела на каждый уровень вложенности.
+/// Язык, синтаксис блоков и комментарии остаются как были, пустые строки выкидываются.
+/// Скрипт с ошибками не форматируется, вместо этого возвращаются ошибки
+pub fn format(src: &str, syntax: SyntaxEnum) -> Result<String, Vec<CompileError>> {
+    let program = parser::parse_with_syntax(src, syntax)?;
+    let style = Style {
+        language: program.language,
+        syntax: program.syntax,
+    };
+    let mut lines = printer::print_lines(&program, style);
+    for comment in parser::comments(src) {
+        //комментарий в конце строки остается за последней командой из этой строки
+        let last = lines
+            .iter()
+            .rposition(|line| line.line == Some(comment.line));
+        if let (Some(idx), false) = (last, comment.own_line) {
+            lines[idx].text.push(' ');
+            lines[idx].text.push_str(&comment.text);
+            continue;
+        }
+        //отдельный комментарий - перед ближайшей строкой, которая в скрипте была ниже него
+        let next_line = lines
+            .iter()
+            .filter_map(|line| line.line)
+            .filter(|line| *line > comment.line)
+            .min();
+        let idx = lines
+            .iter()
+            .position(|line| line.line.is_some() && line.line == next_line)
+            .unwrap_or(lines.len());
+        //после заголовка блока комментарий в теле блока, даже если тело пустое,
+        //а перед концом блока остается внутри блока
+        let indent = match lines[..idx].last() {
+            Some(prev) if prev.opens_block => prev.indent + 1,
+            prev => prev
+                .into_iter()
+                .chain(lines.get(idx))
+                .map(|line| line.indent)
+                .max()
+                .unwrap_or(0),
+        };
+        lines.insert(
+            idx,
+            PrintedLine {
+                indent,
+                text: comment.text,
+                line: Some(comment.line),
+                opens_block: false,
+            },
+        );
+    }
+    Ok(printer::join_lines(&lines))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_comments_and_style() {
+        let src = "# ходит вдоль стены\n\
+                   LOOP   free # пока можно\n\
+                   if WallLeft step  ENDIF\n\
+                   \n\
+                   # на всякий случай\n\
+                   endloop\n\
+                   call Turn # разворот\n\
+                   proc Turn left left endProc\n\
+                   add n 1";
+        let formatted = format(src, SyntaxEnum::Keywords).unwrap();
+        assert_eq!(
+            formatted,
+            "# ходит вдоль стены\n\
+             loop free # пока можно\n\
+             \x20   if wallLeft\n\
+             \x20       step\n\
+             \x20   endIf\n\
+             \x20   # на всякий случай\n\
+             endLoop\n\
+             call Turn # разворот\n\
+             \n\
+             proc Turn\n\
+             \x20   left\n\
+             \x20   left\n\
+             endProc\n\
+             \n\
+             add n 1\n"
+        );
+        assert_eq!(format(&formatted, SyntaxEnum::Keywords), Ok(formatted));
+
+        let src =
+            "#lang ru\n#syntax braces\nпока свободно { ЕСЛИ стенаСлева {направо} иначе {шаг} }";
+        assert_eq!(
+            format(src, SyntaxEnum::Keywords).unwrap(),
+            "#lang ru\n\
+             #syntax braces\n\
+             пока свободно {\n\
+             \x20   если стенаСлева {\n\
+             \x20       направо\n\
+             \x20   } иначе {\n\
+             \x20       шаг\n\
+             \x20   }\n\
+             }\n"
+        );
+        assert!(format("loop step", SyntaxEnum::Keywords).is_err());
+    }
+
+    #[test]
+    fn comment_in_empty_block() {
+        assert_eq!(
+            format("loop free\n # nothing\nendLoop", SyntaxEnum::Keywords).unwrap(),
+            "loop free\n\x20   # nothing\nendLoop\n"
+        );
+        let src = "if free\n# then\nelse\n# else\nendIf\nproc p\n# body\nendProc";
+        assert_eq!(
+            format(src, SyntaxEnum::Keywords).unwrap(),
+            "if free\n\x20   # then\nelse\n\x20   # else\nendIf\n\nproc p\n\x20   # body\nendProc\n"
+        );
+    }
+}
@@ -0,0 +1,526 @@
+use crate::bot::{BotStateEnum, RuntimeError, MAX_CALL_DEPTH, MAX_CONDITION_DEPTH, MAX_REGISTERS};
+use crate::bytecode::{BytecodeError, VERSION};
+use crate::field::{AddBotError, DirectionEnum};
+use crate::parser::CompileError;
+
+/// Язык ключевых слов скрипта и сообщений об ошибках
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LanguageEnum {
+    English,
+    Russian,
+}
+
+impl LanguageEnum {
+    /// Язык по коду из прагмы `#lang ru`
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "en" => Some(LanguageEnum::English),
+            "ru" => Some(LanguageEnum::Russian),
+            _ => None,
+        }
+    }
+}
+
+/// Текст ошибки компиляции вместе с местом в скрипте
+pub fn compile_error(error: &CompileError, language: LanguageEnum) -> String {
+    let location = match language {
+        LanguageEnum::Russian => format!("Строка {}, колонка {}", error.line(), error.column()),
+        LanguageEnum::English => format!("Line {}, column {}", error.line(), error.column()),
+    };
+    format!("{}: {}", location, compile_error_text(error, language))
+}
+
+/// Текст ошибки компиляции без места, для тех, кто пишет место по-своему
+pub fn compile_error_text(error: &CompileError, language: LanguageEnum) -> String {
+    use LanguageEnum::{English, Russian};
+    let token = error.token();
+    match (error, language) {
+        (CompileError::UnknownCommand { .. }, Russian) => {
+            format!("Нет такой комманды: {}", token)
+        }
+        (CompileError::UnknownCommand { .. }, English) => format!("Unknown command: {}", token),
+        (CompileError::UnexpectedBlockEnd { .. }, Russian) => {
+            format!("Что то не так с циклами и ифами: лишний {}", token)
+        }
+        (CompileError::UnexpectedBlockEnd { .. }, English) => {
+            format!("Unexpected {}, it does not close any block", token)
+        }
+        (CompileError::UnclosedBlock { .. }, Russian) => {
+            format!("Похоже есть {} не закрытый", token)
+        }
+        (CompileError::UnclosedBlock { .. }, English) => format!("{} is never closed", token),
+        (CompileError::ExpectedCondition { .. }, Russian) => {
+            format!("Тут должно быть условие, а не {}", token)
+        }
+        (CompileError::ExpectedCondition { .. }, English) => {
+            format!("Expected a condition, found {}", token)
+        }
+        (CompileError::ExpectedNumber { .. }, Russian) => {
+            format!("После {} должно быть число или переменная", token)
+        }
+        (CompileError::ExpectedNumber { .. }, English) => {
+            format!("Expected a number or a variable after {}", token)
+        }
+        (CompileError::NumberTooBig { .. }, Russian) => format!(
+            "Число {} не влезает, можно от {} до {}",
+            token,
+            i32::MIN,
+            i32::MAX
+        ),
+        (CompileError::NumberTooBig { .. }, English) => format!(
+            "Number {} is out of range {}..{}",
+            token,
+            i32::MIN,
+            i32::MAX
+        ),
+        (CompileError::ExpectedName { .. }, Russian) => {
+            format!("После {} должно быть имя", token)
+        }
+        (CompileError::ExpectedName { .. }, English) => {
+            format!("Expected a name after {}", token)
+        }
+        (CompileError::MisplacedProc { .. }, Russian) => {
+            "Процедуры объявляются только на верхнем уровне".to_string()
+        }
+        (CompileError::MisplacedProc { .. }, English) => {
+            "Procedures can only be declared at the top level".to_string()
+        }
+        (CompileError::DuplicateProc { .. }, Russian) => {
+            format!("Процедура {} уже есть", token)
+        }
+        (CompileError::DuplicateProc { .. }, English) => {
+            format!("Procedure {} is already declared", token)
+        }
+        (CompileError::UndefinedProc { .. }, Russian) => {
+            format!("Нет такой процедуры: {}", token)
+        }
+        (CompileError::UndefinedProc { .. }, English) => {
+            format!("Unknown procedure: {}", token)
+        }
+        (CompileError::TooManyRegisters { .. }, Russian) => format!(
+            "Переменных больше {}, {} уже не влезает",
+            MAX_REGISTERS, token
+        ),
+        (CompileError::TooManyRegisters { .. }, English) => format!(
+            "More than {} variables, no room for {}",
+            MAX_REGISTERS, token
+        ),
+        (CompileError::ConditionTooDeep { .. }, Russian) => format!(
+            "Условие после {} вложено глубже {}",
+            token, MAX_CONDITION_DEPTH
+        ),
+        (CompileError::ConditionTooDeep { .. }, English) => format!(
+            "Condition after {} is nested deeper than {}",
+            token, MAX_CONDITION_DEPTH
+        ),
+        (CompileError::UnknownLanguage { .. }, Russian) => {
+            format!("Нет такого языка: {}, есть en и ru", token)
+        }
+        (CompileError::UnknownLanguage { .. }, English) => {
+            format!("Unknown language: {}, use en or ru", token)
+        }
+        (CompileError::UnknownSyntax { .. }, Russian) => {
+            format!("Нет такого синтаксиса: {}, есть keywords и braces", token)
+        }
+        (CompileError::UnknownSyntax { .. }, English) => {
+            format!("Unknown syntax: {}, use keywords or braces", token)
+        }
+        (CompileError::ExpectedBrace { .. }, Russian) => {
+            format!("После {} должна быть {{", token)
+        }
+        (CompileError::ExpectedBrace { .. }, English) => {
+            format!("Expected {{ after {}", token)
+        }
+    }
+}
+
+/// Текст ошибки загрузки байткода. Место - байт или инструкция, если оно известно
+pub fn bytecode_error(error: &BytecodeError, language: LanguageEnum) -> String {
+    use LanguageEnum::{English, Russian};
+    match (error, language) {
+        (BytecodeError::BadBase64, Russian) => "Это не base64".to_string(),
+        (BytecodeError::BadBase64, English) => "Not base64".to_string(),
+        (BytecodeError::BadMagic, Russian) => "Это не байткод бота".to_string(),
+        (BytecodeError::BadMagic, English) => "Not a bot bytecode".to_string(),
+        (BytecodeError::UnsupportedVersion { version }, Russian) => format!(
+            "Байткод версии {}, а поддерживается только {}",
+            version, VERSION
+        ),
+        (BytecodeError::UnsupportedVersion { version }, English) => format!(
+            "Bytecode version {}, only {} is supported",
+            version, VERSION
+        ),
+        (BytecodeError::UnexpectedEnd { offset }, Russian) => {
+            format!("Байт {}: байткод обрывается", offset)
+        }
+        (BytecodeError::UnexpectedEnd { offset }, English) => {
+            format!("Byte {}: bytecode ends unexpectedly", offset)
+        }
+        (BytecodeError::UnknownCode { offset, code }, Russian) => {
+            format!("Байт {}: непонятный код {}", offset, code)
+        }
+        (BytecodeError::UnknownCode { offset, code }, English) => {
+            format!("Byte {}: unknown code {}", offset, code)
+        }
+        (BytecodeError::BadName { offset }, Russian) => {
+            format!("Байт {}: имя переменной не в utf-8", offset)
+        }
+        (BytecodeError::BadName { offset }, English) => {
+            format!("Byte {}: variable name is not utf-8", offset)
+        }
+        (BytecodeError::NameTooLong { register }, Russian) => {
+            format!(
+                "Имя переменной номер {} длиннее {} байт",
+                register,
+                u16::MAX
+            )
+        }
+        (BytecodeError::NameTooLong { register }, English) => {
+            format!(
+                "Variable number {} has a name longer than {} bytes",
+                register,
+                u16::MAX
+            )
+        }
+        (BytecodeError::TrailingBytes { offset }, Russian) => {
+            format!("Байт {}: после программы лишние байты", offset)
+        }
+        (BytecodeError::TrailingBytes { offset }, English) => {
+            format!("Byte {}: extra bytes after the program", offset)
+        }
+        (BytecodeError::ConditionTooDeep { offset }, Russian) => format!(
+            "Байт {}: условие вложено глубже {}",
+            offset, MAX_CONDITION_DEPTH
+        ),
+        (BytecodeError::ConditionTooDeep { offset }, English) => format!(
+            "Byte {}: condition is nested deeper than {}",
+            offset, MAX_CONDITION_DEPTH
+        ),
+        (BytecodeError::TooManyRegisters { count }, Russian) => {
+            format!("Переменных {}, а можно не больше {}", count, MAX_REGISTERS)
+        }
+        (BytecodeError::TooManyRegisters { count }, English) => {
+            format!("{} variables, at most {} are allowed", count, MAX_REGISTERS)
+        }
+        (BytecodeError::BadTarget { ptr, target }, Russian) => {
+            format!("Инструкция {}: переход на {} мимо программы", ptr, target)
+        }
+        (BytecodeError::BadTarget { ptr, target }, English) => {
+            format!(
+                "Instruction {}: jump to {} is outside the program",
+                ptr, target
+            )
+        }
+        (BytecodeError::BadRegister { ptr, register }, Russian) => {
+            format!("Инструкция {}: нет переменной номер {}", ptr, register)
+        }
+        (BytecodeError::BadRegister { ptr, register }, English) => {
+            format!("Instruction {}: no variable number {}", ptr, register)
+        }
+    }
+}
+
+/// Что не так в настройках нового поля
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldSettingEnum {
+    Seed,
+    Width,
+    Height,
+    WallPercent,
+    SayRange,
+}
+
+/// Текст ошибки в настройке нового поля
+pub fn bad_field_setting(setting: FieldSettingEnum, language: LanguageEnum) -> String {
+    use LanguageEnum::{English, Russian};
+    match (setting, language) {
+        (FieldSettingEnum::Seed, Russian) => "Сид кривой",
+        (FieldSettingEnum::Seed, English) => "Bad seed",
+        (FieldSettingEnum::Width, Russian) => "Длина кривая",
+        (FieldSettingEnum::Width, English) => "Bad width",
+        (FieldSettingEnum::Height, Russian) => "Высота кривая",
+        (FieldSettingEnum::Height, English) => "Bad height",
+        (FieldSettingEnum::WallPercent, Russian) => "% стен кривой",
+        (FieldSettingEnum::WallPercent, English) => "Bad wall percent",
+        (FieldSettingEnum::SayRange, Russian) => "Дальность say кривая",
+        (FieldSettingEnum::SayRange, English) => "Bad say range",
+    }
+    .to_string()
+}
+
+/// Почему бот не попал на поле, ошибки компиляции - по одной на строку
+pub fn add_bot_error(error: &AddBotError, language: LanguageEnum) -> String {
+    match (error, language) {
+        (AddBotError::Compile(errors), _) => errors
+            .iter()
+            .map(|err| compile_error(err, language))
+            .collect::<Vec<String>>()
+            .join("\n"),
+        (AddBotError::Bytecode(err), _) => bytecode_error(err, language),
+        (AddBotError::FieldFull, LanguageEnum::Russian) => {
+            "На поле нет свободных клеток".to_string()
+        }
+        (AddBotError::FieldFull, LanguageEnum::English) => {
+            "No free cells left on the field".to_string()
+        }
+    }
+}
+
+/// Текст ошибки бота. Место - строка скрипта, если она известна, иначе адрес команды
+pub fn runtime_error(error: &RuntimeError, language: LanguageEnum) -> String {
+    use LanguageEnum::{English, Russian};
+    let (ptr, line) = match error {
+        RuntimeError::CallStackOverflow { ptr, line }
+        | RuntimeError::Livelock { ptr, line, .. } => (*ptr, *line),
+    };
+    let location = match (line, language) {
+        (Some(line), Russian) => format!("Строка {}", line),
+        (Some(line), English) => format!("Line {}", line),
+        (None, Russian) => format!("Инструкция {}", ptr),
+        (None, English) => format!("Instruction {}", ptr),
+    };
+    let text = match (error, language) {
+        (RuntimeError::CallStackOverflow { .. }, Russian) => format!(
+            "больше {} вложенных call, похоже рекурсия без выхода",
+            MAX_CALL_DEPTH
+        ),
+        (RuntimeError::CallStackOverflow { .. }, English) => format!(
+            "more than {} nested calls, looks like endless recursion",
+            MAX_CALL_DEPTH
+        ),
+        (RuntimeError::Livelock { budget, .. }, Russian) => format!(
+            "за {} команд ни одного действия, похоже бот завис в цикле",
+            budget
+        ),
+        (RuntimeError::Livelock { budget, .. }, English) => format!(
+            "no action in {} instructions, looks like the bot is stuck in a loop",
+            budget
+        ),
+    };
+    format!("{}: {}", location, text)
+}
+
+/// Ошибка бота с номером бота, номера с 1
+pub fn bot_error(idx: usize, error: &RuntimeError, language: LanguageEnum) -> String {
+    match language {
+        LanguageEnum::Russian => format!("Бот {}: {}", idx + 1, runtime_error(error, language)),
+        LanguageEnum::English => format!("Bot {}: {}", idx + 1, runtime_error(error, language)),
+    }
+}
+
+/// Ошибка бота с номером хода, на котором она случилась
+pub fn tick_error(tick: usize, error: &RuntimeError, language: LanguageEnum) -> String {
+    match language {
+        LanguageEnum::Russian => format!("Ход {}: {}", tick, runtime_error(error, language)),
+        LanguageEnum::English => format!("Tick {}: {}", tick, runtime_error(error, language)),
+    }
+}
+
+/// Состояние бота для отладчика
+pub fn bot_state_name(state: BotStateEnum, language: LanguageEnum) -> &'static str {
+    use LanguageEnum::{English, Russian};
+    match (state, language) {
+        (BotStateEnum::Running, Russian) => "работает",
+        (BotStateEnum::Running, English) => "running",
+        (BotStateEnum::Halted, Russian) => "остановлен",
+        (BotStateEnum::Halted, English) => "halted",
+        (BotStateEnum::Crashed, Russian) => "упал",
+        (BotStateEnum::Crashed, English) => "crashed",
+        (BotStateEnum::Blocked, Russian) => "завис",
+        (BotStateEnum::Blocked, English) => "blocked",
+    }
+}
+
+/// Куда смотрит бот, для отладчика
+pub fn direction_name(direction: DirectionEnum, language: LanguageEnum) -> &'static str {
+    use LanguageEnum::{English, Russian};
+    match (direction, language) {
+        (DirectionEnum::Up, Russian) => "вверх",
+        (DirectionEnum::Up, English) => "up",
+        (DirectionEnum::Right, Russian) => "вправо",
+        (DirectionEnum::Right, English) => "right",
+        (DirectionEnum::Down, Russian) => "вниз",
+        (DirectionEnum::Down, English) => "down",
+        (DirectionEnum::Left, Russian) => "влево",
+        (DirectionEnum::Left, English) => "left",
+    }
+}
+
+/// Что не так в аргументах botc и bots-sim
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgsErrorEnum {
+    NoCommand,
+    UnknownCommand(String),
+    UnknownOption(String),
+    MissingValue(String),
+    NotANumber { flag: String, value: String },
+    UnknownLanguage(String),
+    UnknownSyntax(String),
+    UnknownStyle(String),
+    FieldSize { max: i32 },
+    WallPercent,
+    NoScripts,
+    OneScriptOnly,
+}
+
+/// Язык ошибок из `--lang` в аргументах. Смотрится до разбора остальных аргументов,
+/// чтобы и ошибки в них были на этом языке
+pub fn language_from_args(args: &[String]) -> LanguageEnum {
+    args.windows(2)
+        .rev()
+        .filter(|pair| pair[0] == "--lang")
+        .find_map(|pair| LanguageEnum::from_code(&pair[1]))
+        .unwrap_or(LanguageEnum::Russian)
+}
+
+/// Текст ошибки в аргументах botc и bots-sim
+pub fn args_error(error: &ArgsErrorEnum, language: LanguageEnum) -> String {
+    use LanguageEnum::{English, Russian};
+    match (error, language) {
+        (ArgsErrorEnum::NoCommand, Russian) => "Нужна команда: check, disasm или build".to_string(),
+        (ArgsErrorEnum::NoCommand, English) => {
+            "A command is required: check, disasm or build".to_string()
+        }
+        (ArgsErrorEnum::UnknownCommand(command), Russian) => {
+            format!("Нет такой команды: {}", command)
+        }
+        (ArgsErrorEnum::UnknownCommand(command), English) => {
+            format!("Unknown command: {}", command)
+        }
+        (ArgsErrorEnum::UnknownOption(flag), Russian) => format!("Нет такой опции: {}", flag),
+        (ArgsErrorEnum::UnknownOption(flag), English) => format!("Unknown option: {}", flag),
+        (ArgsErrorEnum::MissingValue(flag), Russian) => format!("После {} нужно значение", flag),
+        (ArgsErrorEnum::MissingValue(flag), English) => format!("{} needs a value", flag),
+        (ArgsErrorEnum::NotANumber { flag, value }, Russian) => {
+            format!("После {} должно быть число, а не {}", flag, value)
+        }
+        (ArgsErrorEnum::NotANumber { flag, value }, English) => {
+            format!("{} needs a number, not {}", flag, value)
+        }
+        (ArgsErrorEnum::UnknownLanguage(code), Russian) => {
+            format!("Нет такого языка: {}, есть en и ru", code)
+        }
+        (ArgsErrorEnum::UnknownLanguage(code), English) => {
+            format!("Unknown language: {}, use en or ru", code)
+        }
+        (ArgsErrorEnum::UnknownSyntax(code), Russian) => {
+            format!("Нет такого синтаксиса: {}, есть keywords и braces", code)
+        }
+        (ArgsErrorEnum::UnknownSyntax(code), English) => {
+            format!("Unknown syntax: {}, use keywords or braces", code)
+        }
+        (ArgsErrorEnum::UnknownStyle(code), Russian) => {
+            format!("Нет такого стиля: {}, есть ascii и unicode", code)
+        }
+        (ArgsErrorEnum::UnknownStyle(code), English) => {
+            format!("Unknown style: {}, use ascii or unicode", code)
+        }
+        (ArgsErrorEnum::FieldSize { max }, Russian) => {
+            format!("Поле должно быть от 1x1 до {}x{}", max, max)
+        }
+        (ArgsErrorEnum::FieldSize { max }, English) => {
+            format!("The field must be from 1x1 to {}x{}", max, max)
+        }
+        (ArgsErrorEnum::WallPercent, Russian) => "Процент стен должен быть от 0 до 99".to_string(),
+        (ArgsErrorEnum::WallPercent, English) => "Wall percent must be from 0 to 99".to_string(),
+        (ArgsErrorEnum::NoScripts, Russian) => "Нужен хотя бы один скрипт".to_string(),
+        (ArgsErrorEnum::NoScripts, English) => "At least one script is required".to_string(),
+        (ArgsErrorEnum::OneScriptOnly, Russian) => {
+            "disasm и build работают с одним скриптом".to_string()
+        }
+        (ArgsErrorEnum::OneScriptOnly, English) => {
+            "disasm and build take a single script".to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse;
+
+    #[test]
+    fn messages_in_both_languages() {
+        let errors = parse("loop\n  jump").unwrap_err();
+        assert_eq!(
+            compile_error(&errors[0], LanguageEnum::Russian),
+            "Строка 2, колонка 3: Нет такой комманды: jump"
+        );
+        assert_eq!(
+            compile_error(&errors[0], LanguageEnum::English),
+            "Line 2, column 3: Unknown command: jump"
+        );
+        assert_eq!(
+            errors[0].to_string(),
+            compile_error(&errors[0], LanguageEnum::Russian)
+        );
+        assert_eq!(
+            compile_error_text(&errors[0], LanguageEnum::English),
+            "Unknown command: jump"
+        );
+
+        let error = RuntimeError::Livelock {
+            ptr: 4,
+            line: None,
+            budget: 10,
+        };
+        assert_eq!(
+            runtime_error(&error, LanguageEnum::English),
+            "Instruction 4: no action in 10 instructions, looks like the bot is stuck in a loop"
+        );
+        assert_eq!(
+            error.to_string(),
+            runtime_error(&error, LanguageEnum::Russian)
+        );
+
+        let error = BytecodeError::UnknownCode {
+            offset: 9,
+            code: 200,
+        };
+        assert_eq!(
+            bytecode_error(&error, LanguageEnum::English),
+            "Byte 9: unknown code 200"
+        );
+        assert_eq!(error.to_string(), "Байт 9: непонятный код 200");
+        assert_eq!(
+            add_bot_error(&AddBotError::Bytecode(error), LanguageEnum::English),
+            "Byte 9: unknown code 200"
+        );
+
+        let error = RuntimeError::CallStackOverflow {
+            ptr: 0,
+            line: Some(3),
+        };
+        assert!(bot_error(1, &error, LanguageEnum::English).starts_with("Bot 2: Line 3: "));
+        assert_eq!(
+            bot_state_name(BotStateEnum::Blocked, LanguageEnum::English),
+            "blocked"
+        );
+        assert_eq!(
+            direction_name(DirectionEnum::Left, LanguageEnum::Russian),
+            "влево"
+        );
+    }
+
+    #[test]
+    fn args_errors_follow_lang_flag() {
+        let args: Vec<String> = "--width x --lang en --lang de a.bot"
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+        assert_eq!(language_from_args(&args), LanguageEnum::English);
+        assert_eq!(language_from_args(&args[..2]), LanguageEnum::Russian);
+        let error = ArgsErrorEnum::NotANumber {
+            flag: "--width".to_string(),
+            value: "x".to_string(),
+        };
+        assert_eq!(
+            args_error(&error, LanguageEnum::English),
+            "--width needs a number, not x"
+        );
+        assert_eq!(
+            args_error(&error, LanguageEnum::Russian),
+            "После --width должно быть число, а не x"
+        );
+    }
+}
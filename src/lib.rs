@@ -0,0 +1,15 @@
+//! Движок ботов: разбор скрипта, компиляция, виртуальная машина бота и поле.
+//! Ничего не знает про браузер, веб-интерфейс собирается отдельно с фичей `web`.
+pub mod ast;
+pub mod bot;
+pub mod bytecode;
+pub mod codegen;
+pub mod decompiler;
+pub mod disasm;
+pub mod field;
+pub mod formatter;
+pub mod lang;
+pub mod optimizer;
+pub mod parser;
+pub mod printer;
+pub mod render;
@@ -1,16 +1,19 @@
-mod bot;
-mod field;
-
-use crate::field::FieldCellState;
-use field::Field;
+use bots::bot::{BreakpointEnum, ProgramEndEnum, DEFAULT_TICK_BUDGET};
+use bots::field::{
+    AddBotError, ExecutionModeEnum, Field, FieldCellState, DEFAULT_SAY_RANGE,
+};
+use bots::lang::{self, FieldSettingEnum, LanguageEnum};
+use bots::parser::{CompileError, SyntaxEnum};
+use bots::{bytecode, disasm, formatter};
 use gloo_file::callbacks::FileReader;
 use gloo_file::File;
 use gloo_timers::callback::Interval;
-use web_sys::{Event, HtmlInputElement};
+use web_sys::{Event, HtmlInputElement, HtmlTextAreaElement};
 use yew::{html, html::TargetCast, Component, Context, Html, NodeRef};
 
 pub enum Msg {
-    Loaded(String),
+    /// Содержимое файла и синтаксис по его расширению
+    Loaded(SyntaxEnum, String),
     File(File),
     Create,
     Step,
@@ -18,6 +21,17 @@ pub enum Msg {
     TurnPlay,
     AddSpeed(i32),
     TurnWall(i32, i32),
+    TurnOptimize,
+    TurnTickMode,
+    TurnProgramEnd,
+    TurnBreakpoint(usize, BreakpointEnum),
+    SelectBot(usize),
+    StepBot,
+    TurnLanguage,
+    /// Бот из скрипта в редакторе
+    EditorBot,
+    /// Отформатировать скрипт в редакторе
+    Format,
     DefaultBot
 }
 
@@ -30,7 +44,18 @@ pub struct App {
     width_input_ref: NodeRef,
     height_input_ref: NodeRef,
     walls_input_ref: NodeRef,
+    seed_input_ref: NodeRef,
+    say_range_input_ref: NodeRef,
+    editor_ref: NodeRef,
+    /// Синтаксис скрипта в редакторе, по расширению последнего загруженного файла
+    editor_syntax: SyntaxEnum,
     error_message: String,
+    /// Как понят последний загруженный скрипт: канонический текст и листинг команд
+    listing: String,
+    /// Бот, открытый в отладчике
+    selected_bot: Option<usize>,
+    /// На каком языке показывать ошибки
+    language: LanguageEnum,
     speed: u32,
 
 }
@@ -49,15 +74,27 @@ impl Component for App {
             width_input_ref: NodeRef::default(),
             height_input_ref: NodeRef::default(),
             walls_input_ref: NodeRef::default(),
+            seed_input_ref: NodeRef::default(),
+            say_range_input_ref: NodeRef::default(),
+            editor_ref: NodeRef::default(),
+            editor_syntax: SyntaxEnum::Keywords,
             error_message: "".to_string(),
+            listing: "".to_string(),
+            selected_bot: Option::None,
+            language: LanguageEnum::Russian,
             speed: 10,
         }
     }
 
     fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
-            Msg::Loaded(data) => {
-                self.field.as_mut().unwrap().add_bot(data);
+            Msg::Loaded(syntax, data) => {
+                //скрипт попадает и в редактор, там его можно поправить и добавить еще раз
+                if !bytecode::is_base64(&data) {
+                    Self::get_text_area(&self.editor_ref).set_value(&data);
+                    self.editor_syntax = syntax;
+                }
+                self.add_bot(syntax, data);
                 self.reader = Option::None;
 
                 let el = Self::get_html_element(&self.file_input_ref);
@@ -65,17 +102,39 @@ impl Component for App {
                 true
             }
 
+            Msg::EditorBot => {
+                let data = Self::get_text_area(&self.editor_ref).value();
+                self.add_bot(self.editor_syntax, data);
+                true
+            }
+
+            Msg::Format => {
+                let editor = Self::get_text_area(&self.editor_ref);
+                self.error_message = match formatter::format(&editor.value(), self.editor_syntax) {
+                    Ok(formatted) => {
+                        editor.set_value(&formatted);
+                        "".to_string()
+                    }
+                    Err(errors) => Self::compile_errors(&errors, self.language),
+                };
+                true
+            }
+
             Msg::DefaultBot => {
-                self.field.as_mut().unwrap().add_bot("loop\nloop\nstep\nendLoop\nleftOrRight\nendLoop\nleft".to_string());
+                let added = self.field.as_mut().unwrap().add_bot("loop\nloop\nstep\nendLoop\nleftOrRight\nendLoop\nleft".to_string());
+                if let Err(err) = added {
+                    self.error_message = lang::add_bot_error(&err, self.language);
+                }
                 true
             }
 
             Msg::File(file) => {
                 log::info!("msg::file");
+                let syntax = SyntaxEnum::from_file_name(&file.name());
                 let task = {
                     let link = ctx.link().clone();
                     gloo_file::callbacks::read_as_text(&file, move |res| {
-                        link.send_message(Msg::Loaded(res.unwrap_or_else(|e| e.to_string())))
+                        link.send_message(Msg::Loaded(syntax, res.unwrap_or_else(|e| e.to_string())))
                     })
                 };
                 self.reader = Some(task);
@@ -83,7 +142,17 @@ impl Component for App {
             }
 
             Msg::Create => {
-                self.field = Some(Field::new());
+                //пустой сид - случайный, его потом видно рядом с кнопкой
+                let seed = Self::get_html_element(&self.seed_input_ref).value();
+                self.field = if seed.trim().is_empty() {
+                    Some(Field::new())
+                } else if let Ok(seed) = seed.trim().parse() {
+                    Some(Field::with_seed(seed))
+                } else {
+                    self.error_message = lang::bad_field_setting(FieldSettingEnum::Seed, self.language);
+                    self.field = Option::None;
+                    return true;
+                };
                 let fld = self.field.as_mut().unwrap();
                 if let Ok(width) = Self::get_html_element(&self.width_input_ref)
                     .value()
@@ -92,7 +161,7 @@ impl Component for App {
                 {
                     fld.width = width;
                 } else {
-                    self.error_message = "Длина кривая".to_string();
+                    self.error_message = lang::bad_field_setting(FieldSettingEnum::Width, self.language);
                     self.field = Option::None;
                     return true;
                 }
@@ -103,7 +172,7 @@ impl Component for App {
                 {
                     fld.height = height;
                 } else {
-                    self.error_message = "Высота кривая".to_string();
+                    self.error_message = lang::bad_field_setting(FieldSettingEnum::Height, self.language);
                     self.field = Option::None;
                     return true;
                 }
@@ -114,16 +183,28 @@ impl Component for App {
                 {
                     fld.add_random_wall(walls_percent);
                 } else {
-                    self.error_message = "% стен кривой".to_string();
+                    self.error_message = lang::bad_field_setting(FieldSettingEnum::WallPercent, self.language);
+                    self.field = Option::None;
+                    return true;
+                }
+                if let Ok(say_range) = Self::get_html_element(&self.say_range_input_ref)
+                    .value()
+                    .trim()
+                    .parse()
+                {
+                    fld.say_range = say_range;
+                } else {
+                    self.error_message = lang::bad_field_setting(FieldSettingEnum::SayRange, self.language);
                     self.field = Option::None;
                     return true;
                 }
                 self.error_message = "".to_string();
+                self.selected_bot = Option::None;
                 true
             }
 
             Msg::Step => {
-                self.field.as_mut().unwrap().step();
+                self.do_field_step();
                 true
             }
 
@@ -134,7 +215,7 @@ impl Component for App {
 
             Msg::Tick => {
                 if self.play_is_on {
-                    self.field.as_mut().unwrap().step();
+                    self.do_field_step();
                     true
                 } else {
                     false
@@ -161,6 +242,52 @@ impl Component for App {
                 self.field.as_mut().unwrap().turn_wall(x, y);
                 true
             }
+            Msg::TurnTickMode => {
+                let fld = self.field.as_mut().unwrap();
+                fld.mode = match fld.mode {
+                    ExecutionModeEnum::PerInstruction => ExecutionModeEnum::PerTick {
+                        budget: DEFAULT_TICK_BUDGET,
+                    },
+                    ExecutionModeEnum::PerTick { .. } => ExecutionModeEnum::PerInstruction,
+                };
+                true
+            }
+            Msg::TurnProgramEnd => {
+                let fld = self.field.as_mut().unwrap();
+                fld.program_end = match fld.program_end {
+                    ProgramEndEnum::Loop => ProgramEndEnum::Halt,
+                    ProgramEndEnum::Halt => ProgramEndEnum::Loop,
+                };
+                true
+            }
+            Msg::TurnBreakpoint(idx, breakpoint) => {
+                self.field.as_mut().unwrap().toggle_breakpoint(idx, breakpoint);
+                true
+            }
+            Msg::SelectBot(idx) => {
+                self.selected_bot = Some(idx);
+                true
+            }
+            Msg::StepBot => {
+                let idx = self.selected_bot.unwrap();
+                self.error_message = match self.field.as_mut().unwrap().do_bot_instruction(idx) {
+                    Ok(()) => "".to_string(),
+                    Err(err) => lang::bot_error(idx, &err, self.language),
+                };
+                true
+            }
+            Msg::TurnLanguage => {
+                self.language = match self.language {
+                    LanguageEnum::Russian => LanguageEnum::English,
+                    LanguageEnum::English => LanguageEnum::Russian,
+                };
+                true
+            }
+            Msg::TurnOptimize => {
+                let fld = self.field.as_mut().unwrap();
+                fld.optimize = !fld.optimize;
+                true
+            }
         }
     }
 
@@ -169,16 +296,90 @@ impl Component for App {
         <div style="display:flex;flex-direction:column">
             {self.build_buttons_view(ctx)}
             if !self.error_message.trim().is_empty() {
-              <div style="color:red">{&self.error_message}</div>
+              <div style="color:red;white-space:pre-line">{&self.error_message}</div>
             }
-            <div style="display:flex;flex-direction:column;border-top:solid 1px black;border-left:solid 1px black;width: max-content">
-              {self.build_field_view(ctx)}
+            <div style="display:flex">
+              <div style="display:flex;flex-direction:column;border-top:solid 1px black;border-left:solid 1px black;width: max-content">
+                {self.build_field_view(ctx)}
+              </div>
+              if self.field.is_some() {
+                <div style="display:flex;flex-direction:column;margin-left:10px">
+                  <textarea ref={self.editor_ref.clone()} rows="20" cols="40" style="font-family:monospace" placeholder="Скрипт бота"></textarea>
+                  <div style="display:flex;margin-top:3px">
+                    <button class="button" onclick={ctx.link().callback(|_| Msg::EditorBot)}>{"Добавить бота"}</button>
+                    <button class="button" style="margin-left:5px" onclick={ctx.link().callback(|_| Msg::Format)}>{"Формат"}</button>
+                  </div>
+                </div>
+              }
+              if !self.listing.is_empty() {
+                <pre style="margin:0 0 0 10px;max-width:500px;white-space:pre-wrap;word-break:break-all">{&self.listing}</pre>
+              }
             </div>
+            <div style="display:flex;flex-wrap:wrap">{self.build_sources_view(ctx)}</div>
+            {self.build_debugger_view(ctx)}
         </div> };
     }
 }
 
 impl App {
+    /// Новый бот из скрипта или из байткода в base64, в листинге - как его понял бот
+    fn add_bot(&mut self, syntax: SyntaxEnum, data: String) {
+        let language = self.language;
+        let fld = self.field.as_mut().unwrap();
+        //файл с байткодом в base64 загружаем без компиляции
+        let added = if bytecode::is_base64(&data) {
+            bytecode::from_base64(&data)
+                .map_err(AddBotError::Bytecode)
+                .and_then(|bytes| fld.add_compiled_bot(&bytes))
+        } else {
+            fld.add_bot_with_syntax(data, syntax)
+        }
+        .map_err(|err| lang::add_bot_error(&err, language));
+        let fld = self.field.as_ref().unwrap();
+        self.error_message = match added {
+            Ok(()) => {
+                let bot = fld.get_bot(fld.get_bots_count() - 1).unwrap();
+                //бот, которого нельзя сохранить в байткод, все равно ходит, вместо байткода - почему
+                let encoded = match bytecode::encode(&bot.to_module()) {
+                    Ok(bytes) => bytecode::to_base64(&bytes),
+                    Err(err) => lang::bytecode_error(&err, language),
+                };
+                self.listing = format!(
+                    "{}\n{}\nБайткод:\n{}",
+                    bot.canonical_source(),
+                    disasm::listing(&bot.disassemble()),
+                    encoded
+                );
+                "".to_string()
+            }
+            Err(message) => message,
+        };
+    }
+
+    fn compile_errors(errors: &[CompileError], language: LanguageEnum) -> String {
+        errors
+            .iter()
+            .map(|err| lang::compile_error(err, language))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    fn do_field_step(&mut self) {
+        let fld = self.field.as_mut().unwrap();
+        if let Err(err) = fld.step() {
+            self.error_message = lang::runtime_error(&err, self.language);
+            self.play_is_on = false;
+        }
+        if fld.all_stopped() {
+            self.play_is_on = false;
+        }
+        //бот дошел до точки останова - пауза, и сразу показываем его в отладчике
+        if let Some(idx) = fld.bot_at_breakpoint() {
+            self.play_is_on = false;
+            self.selected_bot = Some(idx);
+        }
+    }
+
     fn create_interval(ctx: &Context<Self>, millis: u32) -> Interval {
         let callback = ctx.link().callback(|_| Msg::Tick);
         Interval::new(millis, move || callback.emit(()))
@@ -188,6 +389,10 @@ impl App {
         node.cast::<HtmlInputElement>().unwrap()
     }
 
+    fn get_text_area(node: &NodeRef) -> HtmlTextAreaElement {
+        node.cast::<HtmlTextAreaElement>().unwrap()
+    }
+
     fn build_field_view(&self, ctx: &Context<Self>) -> Html {
         if self.field.is_none() {
             return html! {};
@@ -224,6 +429,91 @@ impl App {
         };
     }
 
+    /// Скрипты ботов, у каждого подсвечена строка, которую он сейчас выполняет.
+    /// Клик по строке ставит на нее точку останова, клик по заголовку открывает бота в отладчике
+    fn build_sources_view(&self, ctx: &Context<Self>) -> Html {
+        let fld = match self.field.as_ref() {
+            Some(fld) => fld,
+            None => return html! {},
+        };
+        (0..fld.get_bots_count())
+            .map(|idx| {
+                let bot = fld.get_bot(idx).unwrap();
+                let color = fld.get_bot_color(idx).unwrap().to_string();
+                let current_line = bot.current_line();
+                let lines = bot.source().lines().enumerate().map(|(line_idx, text)| {
+                    let line = line_idx + 1;
+                    let style = if current_line == Some(line) {
+                        format!("cursor:pointer;background-color:{};color:white", color)
+                    } else {
+                        "cursor:pointer".to_string()
+                    };
+                    let mark = if bot.has_breakpoint(BreakpointEnum::Line(line)) { "●" } else { " " };
+                    html! {
+                        <div style={style} onclick={ctx.link().callback(move |_| Msg::TurnBreakpoint(idx, BreakpointEnum::Line(line)))}>
+                          {format!("{}{:>3}  {}", mark, line, text)}
+                        </div>
+                    }
+                }).collect::<Html>();
+                let header_style = format!(
+                    "cursor:pointer;border-bottom:3px solid {};{}",
+                    color,
+                    if self.selected_bot == Some(idx) { "font-weight:bold" } else { "" }
+                );
+                html! {
+                    <div style="margin:10px 10px 0 0">
+                      <div style={header_style} onclick={ctx.link().callback(move |_| Msg::SelectBot(idx))}>{format!("Бот {}", idx + 1)}</div>
+                      if bot.source().is_empty() {
+                        <div>{"из байткода, скрипта нет"}</div>
+                      } else {
+                        <pre style="margin:0">{lines}</pre>
+                      }
+                    </div>
+                }
+            }).collect::<Html>()
+    }
+
+    /// Отладчик выбранного бота: где стоит, что в переменных, листинг с точками останова
+    fn build_debugger_view(&self, ctx: &Context<Self>) -> Html {
+        let (fld, idx) = match (self.field.as_ref(), self.selected_bot) {
+            (Some(fld), Some(idx)) if idx < fld.get_bots_count() => (fld, idx),
+            _ => return html! {},
+        };
+        let bot = fld.get_bot(idx).unwrap();
+        let view = bot.view();
+        let (x, y, direction) = fld.get_bot_position(idx).unwrap();
+        let registers = view
+            .registers
+            .iter()
+            .map(|(name, value)| format!("{} = {}", name, value))
+            .collect::<Vec<String>>()
+            .join(", ");
+        let instructions = bot.disassemble().into_iter().map(|instruction| {
+            let ptr = instruction.index as i32;
+            let mark = if bot.has_breakpoint(BreakpointEnum::Instruction(ptr)) { "●" } else { " " };
+            let cursor = if ptr == view.command_ptr { ">" } else { " " };
+            html! {
+                <div style="cursor:pointer" onclick={ctx.link().callback(move |_| Msg::TurnBreakpoint(idx, BreakpointEnum::Instruction(ptr)))}>
+                  {format!("{}{}{}", mark, cursor, instruction)}
+                </div>
+            }
+        }).collect::<Html>();
+        html! {
+            <div style="display:flex;flex-direction:column;margin:10px 0 0 0;padding-top:10px;border-top:1px solid black">
+              <div style="display:flex;align-items:center">
+                <b>{format!("Отладчик: бот {}", idx + 1)}</b>
+                <button class="button" style="margin-left:10px" onclick={ctx.link().callback(|_| Msg::StepBot)}>{"Шаг бота"}</button>
+              </div>
+              <div>{format!("Состояние: {}, клетка ({}, {}), смотрит {}", lang::bot_state_name(view.state, self.language), x, y, lang::direction_name(direction, self.language))}</div>
+              <div>{format!("Команда: {}, строка: {}", view.command_ptr, view.line.map_or("-".to_string(), |line| line.to_string()))}</div>
+              <div>{format!("Переменные: {}", if registers.is_empty() { "-" } else { registers.as_str() })}</div>
+              <div>{format!("Счетчики repeat: {:?}, вложенных call: {}", view.counters, view.call_stack.len())}</div>
+              <div>{format!("Услышал: {:?}", view.inbox)}</div>
+              <pre style="margin:5px 0 0 0">{instructions}</pre>
+            </div>
+        }
+    }
+
     fn build_buttons_view(&self, ctx: &Context<Self>) -> Html {
         let on_change_file_input = {
             ctx.link().callback(move |e: Event| {
@@ -254,15 +544,40 @@ impl App {
                  <div style="display:flex;justify-content:flex-end">
                     {"Стены %:"}
                     <input ref={self.walls_input_ref.clone()} style="width:50px;margin-left:5px"  type="number" value="30" max="90" min="0"/></div>
+                 <div style="display:flex;justify-content:flex-end;margin-top:3px">
+                    {"Сид:"}
+                    <input ref={self.seed_input_ref.clone()} style="width:50px;margin-left:5px" type="text" placeholder="любой"/></div>
+                 <div style="display:flex;justify-content:flex-end;margin-top:3px">
+                    {"Слышно на:"}
+                    <input ref={self.say_range_input_ref.clone()} style="width:50px;margin-left:5px" type="number" value={DEFAULT_SAY_RANGE.to_string()} max="50" min="0"/></div>
               </div>
-              <div style="display:flex; width:100px;margin-left:10px">
+              <div style="display:flex;flex-direction:column;align-items:flex-start; width:100px;margin-left:10px">
                  <button class="button" onclick={ctx.link().callback(|_| Msg::Create)}>{"Создать"}</button>
+                 if let Some(fld) = self.field.as_ref() {
+                   <div style="margin-left:5px">{format!("Сид: {}", fld.seed())}</div>
+                 }
+                 <label style="margin-top:3px">
+                   <input type="checkbox" checked={self.language == LanguageEnum::English} onchange={ctx.link().callback(|_| Msg::TurnLanguage)} />
+                   {"Errors in English"}
+                 </label>
               </div>
              if self.field.is_some() {
                  <div style="display:flex;flex-direction:column; width:200px;margin-left:10px">
                    <div style="display:flex;flex-wrap:no-wrap;margin-bottom:3px">
                         <input type="file" multiple=false onchange={on_change_file_input} ref={self.file_input_ref.clone()} />
                    </div>
+                   <label style="margin-bottom:3px">
+                     <input type="checkbox" checked={self.field.as_ref().unwrap().optimize} onchange={ctx.link().callback(|_| Msg::TurnOptimize)} />
+                     {"Оптимизировать"}
+                   </label>
+                   <label style="margin-bottom:3px">
+                     <input type="checkbox" checked={self.field.as_ref().unwrap().mode != ExecutionModeEnum::PerInstruction} onchange={ctx.link().callback(|_| Msg::TurnTickMode)} />
+                     {"Переходы без хода"}
+                   </label>
+                   <label style="margin-bottom:3px">
+                     <input type="checkbox" checked={self.field.as_ref().unwrap().program_end == ProgramEndEnum::Halt} onchange={ctx.link().callback(|_| Msg::TurnProgramEnd)} />
+                     {"Стоп в конце программы"}
+                   </label>
                    <div style="display:flex;align-items:center; margin-bottom:3px">
                      <div style="margin-right:5px">{"Cкорость:"}</div>
                      {self.speed}
@@ -289,19 +604,3 @@ fn main() {
     wasm_logger::init(wasm_logger::Config::default());
     yew::start_app::<App>();
 }
-#[cfg(test)]
-mod tests {
-    use crate::Field;
-
-    #[test]
-    fn do_test() {
-        let mut fld = Field::new();
-        fld.width = 3;
-        fld.height = 3;
-        //fld.add_random_wall(2);
-        fld.add_bot("loop\nloop\nstep\nendLoop\nright\nendLoop".to_string());
-        loop {
-            fld.step();
-        }
-    }
-}
@@ -0,0 +1,323 @@
+use crate::bot::CommandEnum;
+use crate::codegen::Code;
+
+/// Упрощает программу, не меняя того, какие действия и в каком порядке делает бот:
+/// сворачивает повороты подряд, пускает переходы сразу в конец цепочки goto
+/// и выкидывает команды, до которых не дойти. Тиков на служебные команды
+/// после этого уходит меньше, так что для отладки оптимизацию лучше выключать
+pub fn optimize(mut code: Code) -> Code {
+    //каждый проход только укорачивает программу, так что когда-нибудь это кончится
+    while fold_turns(&mut code) | thread_jumps(&mut code) | remove_unreachable(&mut code) {}
+    code
+}
+
+/// Подряд идущие left/right заменяются на суммарный поворот.
+/// Серия не должна разрываться переходом в ее середину
+fn fold_turns(code: &mut Code) -> bool {
+    let targets = jump_targets(&code.commands);
+    let mut keep = vec![true; code.commands.len()];
+    let mut idx = 0;
+    while idx < code.commands.len() {
+        let start = idx;
+        let mut rotation = 0;
+        while let Some(command) = code.commands.get(idx) {
+            let turn = match command {
+                CommandEnum::TurnRight => 1,
+                CommandEnum::TurnLeft => 3,
+                _ => break,
+            };
+            if idx > start && targets[idx] {
+                break;
+            }
+            rotation = (rotation + turn) % 4;
+            idx += 1;
+        }
+        if idx == start {
+            idx += 1;
+            continue;
+        }
+        let folded = match rotation {
+            0 => vec![],
+            1 => vec![CommandEnum::TurnRight],
+            //разворот - всегда два right, а не первые две команды серии: те могли быть left right
+            2 => vec![CommandEnum::TurnRight, CommandEnum::TurnRight],
+            _ => vec![CommandEnum::TurnLeft],
+        };
+        for (offset, command) in folded.iter().enumerate() {
+            code.commands[start + offset] = command.clone();
+        }
+        for flag in keep[start + folded.len()..idx].iter_mut() {
+            *flag = false;
+        }
+    }
+    compact(code, &keep)
+}
+
+/// Переход на goto сразу ведет туда, куда ведет тот goto.
+/// Переход на следующую команду ничего не делает и выкидывается
+fn thread_jumps(code: &mut Code) -> bool {
+    let len = code.commands.len();
+    let mut changed = false;
+    for idx in 0..len {
+        let Some(mut target) = code.commands[idx].target() else {
+            continue;
+        };
+        //цепочка может оказаться циклом из goto, дальше длины программы не ходим
+        for _ in 0..len {
+            match code.commands.get(target as usize) {
+                Some(CommandEnum::GoTo(next)) if *next != target => target = *next,
+                _ => break,
+            }
+        }
+        if code.commands[idx].target() != Some(target) {
+            code.commands[idx].set_target(target);
+            changed = true;
+        }
+    }
+    let keep: Vec<bool> = code
+        .commands
+        .iter()
+        .enumerate()
+        .map(|(idx, command)| match command {
+            CommandEnum::GoTo(target)
+            | CommandEnum::GoToNE(_, target)
+            | CommandEnum::GoToIf(_, target) => *target != idx as i32 + 1,
+            _ => true,
+        })
+        .collect();
+    compact(code, &keep) | changed
+}
+
+/// Выкидывает команды, до которых нельзя дойти от начала программы
+fn remove_unreachable(code: &mut Code) -> bool {
+    let len = code.commands.len();
+    let mut reachable = vec![false; len];
+    let mut queue = vec![0];
+    while let Some(idx) = queue.pop() {
+        //с конца программы бот уходит на начало
+        if idx >= len || reachable[idx] {
+            continue;
+        }
+        reachable[idx] = true;
+        let command = &code.commands[idx];
+        if let Some(target) = command.target() {
+            queue.push(target as usize);
+        }
+        if !matches!(
+            command,
+            CommandEnum::GoTo(_) | CommandEnum::Return | CommandEnum::Stop
+        ) {
+            queue.push(idx + 1);
+        }
+    }
+    compact(code, &reachable)
+}
+
+/// Куда можно попасть не по порядку: адреса переходов и возвраты из call
+fn jump_targets(commands: &[CommandEnum]) -> Vec<bool> {
+    let mut targets = vec![false; commands.len() + 1];
+    for (idx, command) in commands.iter().enumerate() {
+        if let Some(target) = command.target() {
+            if let Some(flag) = targets.get_mut(target as usize) {
+                *flag = true;
+            }
+        }
+        if let CommandEnum::Call(_) = command {
+            targets[idx + 1] = true;
+        }
+    }
+    targets
+}
+
+/// Оставляет только команды с `keep` и переставляет адреса.
+/// Переход на выкинутую команду ведет на следующую оставшуюся
+fn compact(code: &mut Code, keep: &[bool]) -> bool {
+    if keep.iter().all(|flag| *flag) {
+        return false;
+    }
+    //новый адрес для каждого старого, включая адрес сразу за концом программы
+    let mut new_ptr = Vec::with_capacity(keep.len() + 1);
+    let mut kept = 0;
+    for flag in keep.iter() {
+        new_ptr.push(kept);
+        if *flag {
+            kept += 1;
+        }
+    }
+    new_ptr.push(kept);
+    let commands = std::mem::take(&mut code.commands);
+    let source_map = std::mem::take(&mut code.source_map);
+    for ((mut command, span), _) in commands
+        .into_iter()
+        .zip(source_map)
+        .zip(keep)
+        .filter(|(_, flag)| **flag)
+    {
+        if let Some(target) = command.target() {
+            command.set_target(new_ptr[target as usize]);
+        }
+        code.commands.push(command);
+        code.source_map.push(span);
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ast::{ConditionEnum, SensorEnum};
+    use crate::bot::{Bot, BotActionEnum, Sensors};
+    use crate::codegen::generate;
+    use crate::field::DirectionEnum;
+    use crate::parser::parse;
+
+    const FREE: ConditionEnum = ConditionEnum::Sensor(SensorEnum::Free);
+
+    fn optimized(src: &str) -> Vec<CommandEnum> {
+        optimize(generate(&parse(src).unwrap())).commands
+    }
+
+    #[test]
+    fn folds_turns() {
+        assert_eq!(optimized("left left left"), vec![CommandEnum::TurnRight]);
+        assert_eq!(
+            optimized("step left right step"),
+            vec![CommandEnum::Step; 2]
+        );
+        assert_eq!(
+            optimized("right right right right right left left"),
+            vec![CommandEnum::TurnLeft]
+        );
+        assert_eq!(
+            optimized("right right"),
+            vec![CommandEnum::TurnRight, CommandEnum::TurnRight]
+        );
+        assert_eq!(
+            optimized("step left right right right step"),
+            vec![
+                CommandEnum::Step,
+                CommandEnum::TurnRight,
+                CommandEnum::TurnRight,
+                CommandEnum::Step
+            ]
+        );
+        assert_eq!(
+            optimized("left left"),
+            vec![CommandEnum::TurnRight, CommandEnum::TurnRight]
+        );
+        assert_eq!(
+            optimized("left loop right left endLoop"),
+            vec![
+                CommandEnum::TurnLeft,
+                CommandEnum::GoToNE(FREE, 3),
+                CommandEnum::GoTo(1)
+            ]
+        );
+        //в середину серии ведет переход, сворачивать через него нельзя
+        assert_eq!(
+            optimized("if free left else right endIf right"),
+            vec![
+                CommandEnum::GoToNE(FREE, 3),
+                CommandEnum::TurnLeft,
+                CommandEnum::GoTo(4),
+                CommandEnum::TurnRight,
+                CommandEnum::TurnRight
+            ]
+        );
+    }
+
+    #[test]
+    fn threads_jumps() {
+        let code = Code {
+            commands: vec![
+                CommandEnum::GoToNE(FREE, 3),
+                CommandEnum::Step,
+                CommandEnum::GoTo(0),
+                CommandEnum::GoTo(4),
+                CommandEnum::GoTo(0),
+            ],
+            source_map: vec![None; 5],
+        };
+        assert_eq!(
+            optimize(code).commands,
+            vec![
+                CommandEnum::GoToNE(FREE, 0),
+                CommandEnum::Step,
+                CommandEnum::GoTo(0)
+            ]
+        );
+        //if без else в конце цикла: выход из if сразу уходит на начало цикла
+        assert_eq!(
+            optimized("loop if wallLeft left endIf endLoop"),
+            vec![
+                CommandEnum::GoToNE(FREE, 4),
+                CommandEnum::GoToNE(ConditionEnum::Sensor(SensorEnum::WallLeft), 0),
+                CommandEnum::TurnLeft,
+                CommandEnum::GoTo(0)
+            ]
+        );
+        //пустое тело if ничего не делает
+        assert_eq!(optimized("if free endIf step"), vec![CommandEnum::Step]);
+        let endless = Code {
+            commands: vec![CommandEnum::GoTo(1), CommandEnum::GoTo(0)],
+            source_map: vec![None; 2],
+        };
+        assert_eq!(optimize(endless).commands, vec![CommandEnum::GoTo(0)]);
+    }
+
+    #[test]
+    fn removes_unreachable() {
+        assert_eq!(
+            optimized("proc unused step endProc proc used left endProc call used right"),
+            vec![
+                CommandEnum::Call(3),
+                CommandEnum::TurnRight,
+                CommandEnum::GoTo(5),
+                CommandEnum::TurnLeft,
+                CommandEnum::Return
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_behavior() {
+        let src = "proc turn left left left endProc\n\
+                   set n 0\n\
+                   loop n < 20\n\
+                   if free step else call turn right right endIf\n\
+                   repeat 2 left right endRepeat\n\
+                   inc n endLoop";
+        //для каждого шага - куда был повернут бот относительно начала.
+        //Повороты подряд свернуты, так что сами повороты не сравниваем
+        let steps = |optimize: bool| {
+            let mut bot = Bot::new();
+            bot.set_optimize(optimize);
+            bot.load_from_string(src.to_string()).unwrap();
+            let mut result = vec![];
+            let mut rotation = 0;
+            for _ in 0..300 {
+                let sensors = Sensors {
+                    //в одну сторону от начального направления стена
+                    free_ahead: rotation != 1,
+                    free_behind: true,
+                    wall_left: false,
+                    wall_right: false,
+                    bot_ahead: false,
+                    at_edge: false,
+                    direction: DirectionEnum::Up,
+                };
+                match bot.do_step(&sensors).unwrap() {
+                    BotActionEnum::Step => result.push(rotation),
+                    BotActionEnum::TurnLeft => rotation = (rotation + 3) % 4,
+                    BotActionEnum::TurnRight => rotation = (rotation + 1) % 4,
+                    BotActionEnum::Nop => {}
+                }
+            }
+            result
+        };
+        let plain = steps(false);
+        let fast = steps(true);
+        assert!(fast.len() > plain.len());
+        assert_eq!(plain[..], fast[..plain.len()]);
+    }
+}
@@ -0,0 +1,1016 @@
+use crate::ast::{
+    ActionEnum, Block, CompareEnum, ConditionEnum, If, Loop, NodeEnum, OperandEnum, Proc, Program,
+    Repeat, SensorEnum, Statement,
+};
+use crate::bot::{MAX_CONDITION_DEPTH, MAX_REGISTERS};
+use crate::field::DirectionEnum;
+use crate::lang::{self, LanguageEnum};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OpCodeEnum {
+    Step,
+    TurnLeft,
+    TurnRight,
+    TurnRandom,
+    LoopStart,
+    LoopEnd,
+    RepeatStart,
+    RepeatEnd,
+    ProcStart,
+    ProcEnd,
+    Call,
+    Stop,
+    Set,
+    Inc,
+    Dec,
+    Add,
+    Sub,
+    Say,
+    Listen,
+    Heard,
+    Compare(CompareEnum),
+    /// Слово из букв, цифр и `_`, которое не ключевое и начинается не с цифры
+    Name,
+    Number(i32),
+    /// Все остальное, например `3x` или `!`
+    Unknown,
+    If,
+    Else,
+    EndIf,
+    Sensor(SensorEnum),
+    Not,
+    And,
+    Or,
+    OpenParen,
+    CloseParen,
+    OpenBrace,
+    CloseBrace,
+}
+
+/// Лексема скрипта вместе с тем, где она стоит в исходнике
+#[derive(Clone)]
+struct Token {
+    code: OpCodeEnum,
+    text: String,
+    span: Span,
+}
+
+/// Положение фрагмента в исходнике скрипта.
+/// Строки и колонки считаются с 1, `start`/`end` - смещения в байтах.
+/// Нулевой `Span::default()` - у того, что взялось не из исходника
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompileError {
+    /// Неизвестная команда
+    UnknownCommand { token: String, span: Span },
+    /// else/endIf/endLoop без открывающего if/loop или не от того блока
+    UnexpectedBlockEnd { token: String, span: Span },
+    /// if/else/loop или скобка, которые так и не закрыли
+    UnclosedBlock { token: String, span: Span },
+    /// На месте условия стоит что-то другое
+    ExpectedCondition { token: String, span: Span },
+    /// Нет числа или переменной там, где они нужны
+    ExpectedNumber { token: String, span: Span },
+    /// Число не влезает в i32
+    NumberTooBig { token: String, span: Span },
+    /// После proc/call/set и т.п. нет имени
+    ExpectedName { token: String, span: Span },
+    /// proc внутри другого блока
+    MisplacedProc { token: String, span: Span },
+    /// Вторая процедура с тем же именем
+    DuplicateProc { token: String, span: Span },
+    /// call процедуры, которой нет
+    UndefinedProc { token: String, span: Span },
+    /// Переменных больше, чем `MAX_REGISTERS`
+    TooManyRegisters { token: String, span: Span },
+    /// not/and/or в условии вложены глубже `MAX_CONDITION_DEPTH`
+    ConditionTooDeep { token: String, span: Span },
+    /// В прагме `#lang` язык, которого нет
+    UnknownLanguage { token: String, span: Span },
+    /// В прагме `#syntax` синтаксис, которого нет
+    UnknownSyntax { token: String, span: Span },
+    /// В синтаксисе со скобками после if/while/repeat/proc/else нет `{`
+    ExpectedBrace { token: String, span: Span },
+}
+
+impl CompileError {
+    pub fn line(&self) -> usize {
+        self.span().line
+    }
+
+    pub fn column(&self) -> usize {
+        self.span().column
+    }
+
+    pub fn token(&self) -> &str {
+        match self {
+            CompileError::UnknownCommand { token, .. }
+            | CompileError::UnexpectedBlockEnd { token, .. }
+            | CompileError::UnclosedBlock { token, .. }
+            | CompileError::ExpectedCondition { token, .. }
+            | CompileError::ExpectedNumber { token, .. }
+            | CompileError::NumberTooBig { token, .. }
+            | CompileError::ExpectedName { token, .. }
+            | CompileError::MisplacedProc { token, .. }
+            | CompileError::DuplicateProc { token, .. }
+            | CompileError::UndefinedProc { token, .. }
+            | CompileError::TooManyRegisters { token, .. }
+            | CompileError::ConditionTooDeep { token, .. }
+            | CompileError::UnknownLanguage { token, .. }
+            | CompileError::UnknownSyntax { token, .. }
+            | CompileError::ExpectedBrace { token, .. } => token,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            CompileError::UnknownCommand { span, .. }
+            | CompileError::UnexpectedBlockEnd { span, .. }
+            | CompileError::UnclosedBlock { span, .. }
+            | CompileError::ExpectedCondition { span, .. }
+            | CompileError::ExpectedNumber { span, .. }
+            | CompileError::NumberTooBig { span, .. }
+            | CompileError::ExpectedName { span, .. }
+            | CompileError::MisplacedProc { span, .. }
+            | CompileError::DuplicateProc { span, .. }
+            | CompileError::UndefinedProc { span, .. }
+            | CompileError::TooManyRegisters { span, .. }
+            | CompileError::ConditionTooDeep { span, .. }
+            | CompileError::UnknownLanguage { span, .. }
+            | CompileError::UnknownSyntax { span, .. }
+            | CompileError::ExpectedBrace { span, .. } => *span,
+        }
+    }
+}
+
+/// По умолчанию ошибки по-русски, на другом языке - через `lang::compile_error`
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", lang::compile_error(self, LanguageEnum::Russian))
+    }
+}
+
+/// Ключевые слова: код, как слово пишется по-английски и по-русски.
+/// Скрипт пишется на одном из наборов, по умолчанию на английском,
+/// русский включается прагмой `#lang ru`
+const KEYWORDS: &[(OpCodeEnum, &str, &str)] = &[
+    (OpCodeEnum::If, "if", "если"),
+    (OpCodeEnum::Else, "else", "иначе"),
+    (OpCodeEnum::EndIf, "endIf", "конецЕсли"),
+    (OpCodeEnum::Step, "step", "шаг"),
+    (OpCodeEnum::TurnLeft, "left", "налево"),
+    (OpCodeEnum::TurnRight, "right", "направо"),
+    (OpCodeEnum::TurnRandom, "leftOrRight", "налевоИлиНаправо"),
+    (OpCodeEnum::LoopStart, "loop", "пока"),
+    //в синтаксисе со скобками цикл привычнее писать так, в синтаксисе с ключевыми словами
+    //это слово не занято и может быть именем
+    (OpCodeEnum::LoopStart, "while", "пока"),
+    (OpCodeEnum::LoopEnd, "endLoop", "конецПока"),
+    (OpCodeEnum::RepeatStart, "repeat", "повтор"),
+    (OpCodeEnum::RepeatEnd, "endRepeat", "конецПовтора"),
+    (OpCodeEnum::ProcStart, "proc", "процедура"),
+    (OpCodeEnum::ProcEnd, "endProc", "конецПроцедуры"),
+    (OpCodeEnum::Call, "call", "вызвать"),
+    (OpCodeEnum::Stop, "stop", "стоп"),
+    (OpCodeEnum::Set, "set", "присвоить"),
+    (OpCodeEnum::Inc, "inc", "увеличить"),
+    (OpCodeEnum::Dec, "dec", "уменьшить"),
+    (OpCodeEnum::Add, "add", "прибавить"),
+    (OpCodeEnum::Sub, "sub", "вычесть"),
+    (OpCodeEnum::Say, "say", "сказать"),
+    (OpCodeEnum::Listen, "listen", "слушать"),
+    (OpCodeEnum::Heard, "heard", "услышал"),
+    (OpCodeEnum::Sensor(SensorEnum::Free), "free", "свободно"),
+    (
+        OpCodeEnum::Sensor(SensorEnum::FreeBehind),
+        "freeBehind",
+        "сзадиСвободно",
+    ),
+    (
+        OpCodeEnum::Sensor(SensorEnum::WallLeft),
+        "wallLeft",
+        "стенаСлева",
+    ),
+    (
+        OpCodeEnum::Sensor(SensorEnum::WallRight),
+        "wallRight",
+        "стенаСправа",
+    ),
+    (
+        OpCodeEnum::Sensor(SensorEnum::BotAhead),
+        "botAhead",
+        "ботВпереди",
+    ),
+    (OpCodeEnum::Sensor(SensorEnum::AtEdge), "atEdge", "край"),
+    (
+        OpCodeEnum::Sensor(SensorEnum::Facing(DirectionEnum::Up)),
+        "facingNorth",
+        "наСевер",
+    ),
+    (
+        OpCodeEnum::Sensor(SensorEnum::Facing(DirectionEnum::Right)),
+        "facingEast",
+        "наВосток",
+    ),
+    (
+        OpCodeEnum::Sensor(SensorEnum::Facing(DirectionEnum::Down)),
+        "facingSouth",
+        "наЮг",
+    ),
+    (
+        OpCodeEnum::Sensor(SensorEnum::Facing(DirectionEnum::Left)),
+        "facingWest",
+        "наЗапад",
+    ),
+    (OpCodeEnum::Not, "not", "не"),
+    (OpCodeEnum::And, "and", "и"),
+    (OpCodeEnum::Or, "or", "или"),
+];
+
+/// Ключевые слова не зависят от регистра: `endif`, `ENDIF` и `endIf` - одно и то же
+fn keyword(text: &str, language: LanguageEnum, syntax: SyntaxEnum) -> Option<OpCodeEnum> {
+    let text = text.to_lowercase();
+    KEYWORDS
+        .iter()
+        .filter(|(_, english, _)| *english != "while" || syntax == SyntaxEnum::Braces)
+        .find(|(_, english, russian)| match language {
+            LanguageEnum::English => english.to_lowercase() == text,
+            LanguageEnum::Russian => russian.to_lowercase() == text,
+        })
+        .map(|(code, _, _)| *code)
+}
+
+/// Ключевое слово `english` так, как оно пишется на языке `language`
+pub fn translate_keyword(english: &str, language: LanguageEnum) -> &str {
+    match (
+        language,
+        KEYWORDS.iter().find(|(_, other, _)| *other == english),
+    ) {
+        (LanguageEnum::Russian, Some((_, _, russian))) => russian,
+        _ => english,
+    }
+}
+
+/// Комментарий из скрипта, `text` - все от `#` до конца строки
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    pub line: usize,
+    pub text: String,
+    /// В строке нет ничего, кроме комментария
+    pub own_line: bool,
+}
+
+/// Все комментарии скрипта по порядку, для `formatter`
+pub fn comments(src: &str) -> Vec<Comment> {
+    lex(src, SyntaxEnum::Keywords).comments
+}
+
+/// Как в скрипте обозначаются блоки
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyntaxEnum {
+    /// `loop .. endLoop`, `if .. else .. endIf`
+    Keywords,
+    /// `while free { step }`, `if wallLeft { right } else { left }`
+    Braces,
+}
+
+impl SyntaxEnum {
+    /// Синтаксис по коду из прагмы `#syntax braces`
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "keywords" => Some(SyntaxEnum::Keywords),
+            "braces" => Some(SyntaxEnum::Braces),
+            _ => None,
+        }
+    }
+
+    /// Синтаксис по имени файла: `.bbot` - со скобками, остальное - с ключевыми словами
+    pub fn from_file_name(name: &str) -> Self {
+        if name.ends_with(".bbot") {
+            SyntaxEnum::Braces
+        } else {
+            SyntaxEnum::Keywords
+        }
+    }
+}
+
+#[derive(PartialEq)]
+enum ParserStateEnum {
+    Proc,
+    Loop,
+    Repeat,
+    If,
+    Else,
+    Root,
+}
+
+/// Разбирает скрипт в дерево. Ошибки не прерывают разбор,
+/// так что за один проход находятся все
+pub fn parse(src: &str) -> Result<Program, Vec<CompileError>> {
+    parse_with_syntax(src, SyntaxEnum::Keywords)
+}
+
+/// То же, что `parse`, но с синтаксисом `syntax`, если в скрипте нет прагмы `#syntax`.
+/// Оба синтаксиса дают одно и то же дерево
+pub fn parse_with_syntax(src: &str, syntax: SyntaxEnum) -> Result<Program, Vec<CompileError>> {
+    let mut lexed = lex(src, syntax);
+    lexed.tokens.reverse();
+    let mut parser = Parser {
+        tokens: lexed.tokens,
+        syntax: lexed.syntax,
+        errors: lexed.errors,
+        procs: vec![],
+        calls: vec![],
+        registers: vec![],
+    };
+    let (main, _) = parser.parse_block(ParserStateEnum::Root, None);
+    parser.check_calls();
+    if !parser.errors.is_empty() {
+        return Err(parser.errors);
+    }
+    Ok(Program {
+        main,
+        procs: parser.procs,
+        registers: parser.registers,
+        language: lexed.language,
+        syntax: parser.syntax,
+    })
+}
+
+/// Разбивает исходник на слова. Пробелы, табы, переводы строк (в том числе `\r\n`)
+/// только разделяют команды, `#` начинает комментарий до конца строки.
+/// Скобки и операторы сравнения всегда отдельные слова.
+/// Прагмы в комментариях действуют на весь скрипт: `#lang ru` или `#lang en` выбирает
+/// язык ключевых слов, `#syntax braces` или `#syntax keywords` - синтаксис блоков.
+/// Прагмой считается только отдельный комментарий до первой команды
+fn lex(src: &str, syntax: SyntaxEnum) -> Lexed {
+    let mut words = Vec::new();
+    let mut errors = Vec::new();
+    let mut comments = Vec::new();
+    //строка последнего слова, чтобы отличать комментарий в конце строки от отдельного
+    let mut last_word_line = 0;
+    let mut language = LanguageEnum::English;
+    let mut syntax = syntax;
+    let mut line = 1;
+    let mut line_start = 0;
+    let mut chars = src.char_indices().peekable();
+    while let Some((start, ch)) = chars.next() {
+        if ch == '\n' {
+            line += 1;
+            line_start = start + 1;
+            continue;
+        }
+        if ch.is_whitespace() {
+            continue;
+        }
+        if ch == '#' {
+            let mut end = start + 1;
+            while let Some((idx, ch)) = chars.next_if(|(_, ch)| *ch != '\n') {
+                end = idx + ch.len_utf8();
+            }
+            let comment = src[start + 1..end].trim_end();
+            comments.push(Comment {
+                line,
+                text: src[start..end].trim_end().to_string(),
+                own_line: last_word_line != line,
+            });
+            let span = Span {
+                line,
+                column: src[line_start..start].chars().count() + 1,
+                start,
+                end,
+            };
+            if !words.is_empty() {
+                continue;
+            }
+            if let Some(code) = comment.strip_prefix("lang ") {
+                let code = code.trim();
+                match LanguageEnum::from_code(code) {
+                    Some(pragma) => language = pragma,
+                    None => errors.push(CompileError::UnknownLanguage {
+                        token: code.to_string(),
+                        span,
+                    }),
+                }
+            } else if let Some(code) = comment.strip_prefix("syntax ") {
+                let code = code.trim();
+                match SyntaxEnum::from_code(code) {
+                    Some(pragma) => syntax = pragma,
+                    None => errors.push(CompileError::UnknownSyntax {
+                        token: code.to_string(),
+                        span,
+                    }),
+                }
+            }
+            continue;
+        }
+        let is_operator = |ch: char| "<>=!".contains(ch);
+        let is_delimiter = |ch: char| ch.is_whitespace() || "#(){}".contains(ch) || is_operator(ch);
+        let mut end = start + ch.len_utf8();
+        if is_operator(ch) {
+            while let Some((idx, ch)) = chars.next_if(|(_, ch)| is_operator(*ch)) {
+                end = idx + ch.len_utf8();
+            }
+        } else if !is_delimiter(ch) {
+            while let Some((idx, ch)) = chars.next_if(|(_, ch)| !is_delimiter(*ch)) {
+                end = idx + ch.len_utf8();
+            }
+        }
+        let text = &src[start..end];
+        let span = Span {
+            line,
+            column: src[line_start..start].chars().count() + 1,
+            start,
+            end,
+        };
+        last_word_line = line;
+        words.push((text, span));
+    }
+    let tokens = words
+        .into_iter()
+        .map(|(text, span)| {
+            let code = match text {
+                "<" => OpCodeEnum::Compare(CompareEnum::Less),
+                "<=" => OpCodeEnum::Compare(CompareEnum::LessOrEqual),
+                ">" => OpCodeEnum::Compare(CompareEnum::Greater),
+                ">=" => OpCodeEnum::Compare(CompareEnum::GreaterOrEqual),
+                "==" => OpCodeEnum::Compare(CompareEnum::Equal),
+                "!=" => OpCodeEnum::Compare(CompareEnum::NotEqual),
+                "(" => OpCodeEnum::OpenParen,
+                ")" => OpCodeEnum::CloseParen,
+                "{" => OpCodeEnum::OpenBrace,
+                "}" => OpCodeEnum::CloseBrace,
+                other => match (keyword(other, language, syntax), other.parse()) {
+                    (Some(code), _) => code,
+                    (None, Ok(number)) => OpCodeEnum::Number(number),
+                    //число, которое не влезло: ошибка, а дальше разбираем как 0,
+                    //чтобы не сыпались ошибки про то, что числа нет
+                    (None, Err(_)) if is_number(other) => {
+                        errors.push(CompileError::NumberTooBig {
+                            token: other.to_string(),
+                            span,
+                        });
+                        OpCodeEnum::Number(0)
+                    }
+                    (None, Err(_)) if is_name(other) => OpCodeEnum::Name,
+                    (None, Err(_)) => OpCodeEnum::Unknown,
+                },
+            };
+            Token {
+                code,
+                text: text.to_string(),
+                span,
+            }
+        })
+        .collect();
+    Lexed {
+        tokens,
+        language,
+        syntax,
+        comments,
+        errors,
+    }
+}
+
+/// Целое число, может быть с минусом
+fn is_number(text: &str) -> bool {
+    let digits = text.strip_prefix('-').unwrap_or(text);
+    !digits.is_empty() && digits.chars().all(|ch| ch.is_ascii_digit())
+}
+
+/// Имя переменной или процедуры: буквы, цифры и `_`, первой не цифра
+fn is_name(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .is_some_and(|ch| ch.is_alphabetic() || ch == '_')
+        && chars.all(|ch| ch.is_alphanumeric() || ch == '_')
+}
+
+/// Что получилось из исходника после `lex`
+struct Lexed {
+    tokens: Vec<Token>,
+    /// Язык и синтаксис с учетом прагм
+    language: LanguageEnum,
+    syntax: SyntaxEnum,
+    comments: Vec<Comment>,
+    errors: Vec<CompileError>,
+}
+
+/// То, что парсер копит за весь проход
+struct Parser {
+    /// Токены лежат в обратном порядке, следующий - последний в векторе
+    tokens: Vec<Token>,
+    syntax: SyntaxEnum,
+    errors: Vec<CompileError>,
+    procs: Vec<Proc>,
+    /// Имена из всех call, проверяются в конце, когда известны все процедуры
+    calls: Vec<Token>,
+    /// Имена переменных, номер в векторе - номер регистра
+    registers: Vec<String>,
+}
+
+impl Parser {
+    /// Снимает следующий токен, только если его код подходит
+    fn pop_matching(&mut self, predicate: impl FnOnce(OpCodeEnum) -> bool) -> Option<Token> {
+        if predicate(self.tokens.last()?.code) {
+            self.tokens.pop()
+        } else {
+            None
+        }
+    }
+
+    /// Снимает следующий токен, только если это `code`
+    fn pop_if_code(&mut self, code: OpCodeEnum) -> Option<Token> {
+        self.pop_matching(|next| next == code)
+    }
+
+    /// Разбирает токены до конца блока `state`.
+    /// Вместе с блоком возвращается токен, которым он закончился
+    fn parse_block(
+        &mut self,
+        state: ParserStateEnum,
+        opener: Option<&Token>,
+    ) -> (Block, Option<Token>) {
+        let mut result = Block::default();
+        while let Some(token) = self.tokens.pop() {
+            let node = match token.code {
+                OpCodeEnum::TurnLeft => NodeEnum::Action(ActionEnum::TurnLeft),
+                OpCodeEnum::TurnRight => NodeEnum::Action(ActionEnum::TurnRight),
+                OpCodeEnum::TurnRandom => NodeEnum::Action(ActionEnum::TurnRandom),
+                OpCodeEnum::Step => NodeEnum::Action(ActionEnum::Step),
+                OpCodeEnum::Stop => NodeEnum::Stop,
+                OpCodeEnum::If => {
+                    let condition = self.parse_condition(&token);
+                    self.open_block(&token);
+                    let (then_block, end) = self.parse_block(ParserStateEnum::If, Some(&token));
+                    //в синтаксисе со скобками else идет уже после закрывающей скобки
+                    let else_token = match (self.syntax, end) {
+                        (SyntaxEnum::Keywords, end) => {
+                            end.filter(|end| end.code == OpCodeEnum::Else)
+                        }
+                        (SyntaxEnum::Braces, Some(_)) => self.pop_if_code(OpCodeEnum::Else),
+                        (SyntaxEnum::Braces, None) => None,
+                    };
+                    let else_block = else_token.map(|else_token| {
+                        self.open_block(&else_token);
+                        self.parse_block(ParserStateEnum::Else, Some(&else_token)).0
+                    });
+                    NodeEnum::If(If {
+                        condition,
+                        then_block,
+                        else_block,
+                    })
+                }
+                OpCodeEnum::LoopStart => {
+                    let condition = self.parse_condition(&token);
+                    self.open_block(&token);
+                    let (body, _) = self.parse_block(ParserStateEnum::Loop, Some(&token));
+                    NodeEnum::Loop(Loop { condition, body })
+                }
+                OpCodeEnum::RepeatStart => {
+                    let count = self.parse_operand(&token);
+                    self.open_block(&token);
+                    let (body, _) = self.parse_block(ParserStateEnum::Repeat, Some(&token));
+                    NodeEnum::Repeat(Repeat { count, body })
+                }
+                OpCodeEnum::ProcStart => {
+                    self.parse_proc(&token, state == ParserStateEnum::Root);
+                    continue;
+                }
+                OpCodeEnum::Call => match self.parse_name(&token) {
+                    Some(name) => {
+                        let node = NodeEnum::Call(name.text.clone());
+                        self.calls.push(name);
+                        node
+                    }
+                    None => continue,
+                },
+                OpCodeEnum::Set | OpCodeEnum::Add | OpCodeEnum::Sub => {
+                    let register = self.parse_register(&token);
+                    let value = self.parse_operand(&token);
+                    match token.code {
+                        OpCodeEnum::Set => NodeEnum::Set(register, value),
+                        OpCodeEnum::Add => NodeEnum::Add(register, value),
+                        _ => NodeEnum::Sub(register, value),
+                    }
+                }
+                OpCodeEnum::Inc => NodeEnum::Inc(self.parse_register(&token)),
+                OpCodeEnum::Dec => NodeEnum::Dec(self.parse_register(&token)),
+                OpCodeEnum::Say => NodeEnum::Say(self.parse_operand(&token)),
+                OpCodeEnum::Listen => NodeEnum::Listen(self.parse_register(&token)),
+                OpCodeEnum::Else
+                | OpCodeEnum::EndIf
+                | OpCodeEnum::LoopEnd
+                | OpCodeEnum::RepeatEnd
+                | OpCodeEnum::ProcEnd
+                | OpCodeEnum::CloseBrace => {
+                    let closes = match (self.syntax, token.code) {
+                        (SyntaxEnum::Braces, OpCodeEnum::CloseBrace) => {
+                            state != ParserStateEnum::Root
+                        }
+                        (SyntaxEnum::Braces, _) | (_, OpCodeEnum::CloseBrace) => false,
+                        (_, OpCodeEnum::Else) => state == ParserStateEnum::If,
+                        (_, OpCodeEnum::EndIf) => {
+                            state == ParserStateEnum::If || state == ParserStateEnum::Else
+                        }
+                        (_, OpCodeEnum::LoopEnd) => state == ParserStateEnum::Loop,
+                        (_, OpCodeEnum::RepeatEnd) => state == ParserStateEnum::Repeat,
+                        _ => state == ParserStateEnum::Proc,
+                    };
+                    if closes {
+                        result.end = Some(token.span);
+                        return (result, Some(token));
+                    }
+                    self.errors.push(CompileError::UnexpectedBlockEnd {
+                        token: token.text,
+                        span: token.span,
+                    });
+                    continue;
+                }
+                OpCodeEnum::Name
+                | OpCodeEnum::Compare(_)
+                | OpCodeEnum::Number(_)
+                | OpCodeEnum::Unknown
+                | OpCodeEnum::Sensor(_)
+                | OpCodeEnum::Heard
+                | OpCodeEnum::Not
+                | OpCodeEnum::And
+                | OpCodeEnum::Or
+                | OpCodeEnum::OpenParen
+                | OpCodeEnum::CloseParen
+                | OpCodeEnum::OpenBrace => {
+                    self.errors.push(CompileError::UnknownCommand {
+                        token: token.text,
+                        span: token.span,
+                    });
+                    continue;
+                }
+            };
+            result.statements.push(Statement {
+                node,
+                span: token.span,
+            });
+        }
+        if let Some(opener) = opener {
+            self.errors.push(CompileError::UnclosedBlock {
+                token: opener.text.clone(),
+                span: opener.span,
+            });
+        }
+        (result, None)
+    }
+
+    /// В синтаксисе со скобками блок после `keyword` начинается с `{`
+    fn open_block(&mut self, keyword: &Token) {
+        if self.syntax == SyntaxEnum::Braces && self.pop_if_code(OpCodeEnum::OpenBrace).is_none() {
+            self.errors.push(CompileError::ExpectedBrace {
+                token: keyword.text.clone(),
+                span: keyword.span,
+            });
+        }
+    }
+
+    /// proc name .. endProc, процедуры бывают только на верхнем уровне
+    fn parse_proc(&mut self, token: &Token, at_root: bool) {
+        let name = self.parse_name(token);
+        self.open_block(token);
+        if !at_root {
+            self.errors.push(CompileError::MisplacedProc {
+                token: token.text.clone(),
+                span: token.span,
+            });
+        }
+        let (body, _) = self.parse_block(ParserStateEnum::Proc, Some(token));
+        match name {
+            Some(name) if self.procs.iter().any(|other| other.name == name.text) => {
+                self.errors.push(CompileError::DuplicateProc {
+                    token: name.text,
+                    span: name.span,
+                });
+            }
+            Some(name) if at_root => self.procs.push(Proc {
+                name: name.text,
+                span: token.span,
+                body,
+            }),
+            _ => {}
+        }
+    }
+
+    /// Все call должны ссылаться на объявленные процедуры
+    fn check_calls(&mut self) {
+        for name in self.calls.iter() {
+            if !self.procs.iter().any(|proc| proc.name == name.text) {
+                self.errors.push(CompileError::UndefinedProc {
+                    token: name.text.clone(),
+                    span: name.span,
+                });
+            }
+        }
+    }
+
+    /// Имя после proc/call/set и т.п.
+    fn parse_name(&mut self, keyword: &Token) -> Option<Token> {
+        let name = self.pop_if_code(OpCodeEnum::Name);
+        if name.is_none() {
+            self.errors.push(CompileError::ExpectedName {
+                token: keyword.text.clone(),
+                span: keyword.span,
+            });
+        }
+        name
+    }
+
+    /// Условие сразу после if/loop. Если его нет - проверяется свободная клетка впереди.
+    /// `not` связывает сильнее `and`, `and` сильнее `or`, порядок можно задать скобками.
+    /// Кроме датчиков можно сравнивать переменные и числа: `x > 2`, `x != y`,
+    /// и проверять, что слышно: `heard 1`
+    fn parse_condition(&mut self, keyword: &Token) -> ConditionEnum {
+        //имя без сравнения после него - это уже следующая команда, а не условие
+        let mut next = self.tokens.iter().rev().map(|token| token.code);
+        let condition = match (next.next(), next.next()) {
+            (
+                Some(
+                    OpCodeEnum::Sensor(_)
+                    | OpCodeEnum::Heard
+                    | OpCodeEnum::Not
+                    | OpCodeEnum::OpenParen,
+                ),
+                _,
+            )
+            | (Some(OpCodeEnum::Name | OpCodeEnum::Number(_)), Some(OpCodeEnum::Compare(_))) => {
+                self.parse_or(keyword)
+            }
+            _ => ConditionEnum::Sensor(SensorEnum::Free),
+        };
+        //более глубокое условие не загрузится обратно из байткода
+        if condition.depth() > MAX_CONDITION_DEPTH {
+            self.errors.push(CompileError::ConditionTooDeep {
+                token: keyword.text.clone(),
+                span: keyword.span,
+            });
+        }
+        condition
+    }
+
+    fn parse_or(&mut self, prev: &Token) -> ConditionEnum {
+        let mut result = self.parse_and(prev);
+        while let Some(operator) = self.pop_if_code(OpCodeEnum::Or) {
+            let right = self.parse_and(&operator);
+            result = ConditionEnum::Or(Box::new(result), Box::new(right));
+        }
+        result
+    }
+
+    fn parse_and(&mut self, prev: &Token) -> ConditionEnum {
+        let mut result = self.parse_not(prev);
+        while let Some(operator) = self.pop_if_code(OpCodeEnum::And) {
+            let right = self.parse_not(&operator);
+            result = ConditionEnum::And(Box::new(result), Box::new(right));
+        }
+        result
+    }
+
+    /// `prev` - токен перед условием, на него указывает ошибка, если скрипт кончился
+    fn parse_not(&mut self, prev: &Token) -> ConditionEnum {
+        let Some(token) = self.tokens.pop() else {
+            self.errors.push(CompileError::ExpectedCondition {
+                token: prev.text.clone(),
+                span: prev.span,
+            });
+            return ConditionEnum::Sensor(SensorEnum::Free);
+        };
+        match token.code {
+            OpCodeEnum::Sensor(sensor) => ConditionEnum::Sensor(sensor),
+            OpCodeEnum::Heard => ConditionEnum::Heard(self.parse_operand(&token)),
+            OpCodeEnum::Name | OpCodeEnum::Number(_) => {
+                let left = self.operand(token.clone());
+                let Some(operator) =
+                    self.pop_matching(|code| matches!(code, OpCodeEnum::Compare(_)))
+                else {
+                    self.errors.push(CompileError::ExpectedCondition {
+                        token: token.text,
+                        span: token.span,
+                    });
+                    return ConditionEnum::Sensor(SensorEnum::Free);
+                };
+                let OpCodeEnum::Compare(compare) = operator.code else {
+                    unreachable!()
+                };
+                let right = self.parse_operand(&operator);
+                ConditionEnum::Compare(left, compare, right)
+            }
+            OpCodeEnum::Not => ConditionEnum::Not(Box::new(self.parse_not(&token))),
+            OpCodeEnum::OpenParen => {
+                let result = self.parse_or(&token);
+                if self.pop_if_code(OpCodeEnum::CloseParen).is_none() {
+                    self.errors.push(CompileError::UnclosedBlock {
+                        token: token.text,
+                        span: token.span,
+                    });
+                }
+                result
+            }
+            _ => {
+                self.errors.push(CompileError::ExpectedCondition {
+                    token: token.text.clone(),
+                    span: token.span,
+                });
+                self.tokens.push(token);
+                ConditionEnum::Sensor(SensorEnum::Free)
+            }
+        }
+    }
+
+    /// Переменная после set/inc/dec/add/sub/listen
+    fn parse_register(&mut self, keyword: &Token) -> usize {
+        match self.parse_name(keyword) {
+            Some(name) => self.register(name),
+            None => 0,
+        }
+    }
+
+    /// Число или переменная после `prev`
+    fn parse_operand(&mut self, prev: &Token) -> OperandEnum {
+        match self.pop_matching(|code| matches!(code, OpCodeEnum::Name | OpCodeEnum::Number(_))) {
+            Some(token) => self.operand(token),
+            None => {
+                self.errors.push(CompileError::ExpectedNumber {
+                    token: prev.text.clone(),
+                    span: prev.span,
+                });
+                OperandEnum::Value(0)
+            }
+        }
+    }
+
+    fn operand(&mut self, token: Token) -> OperandEnum {
+        match token.code {
+            OpCodeEnum::Number(value) => OperandEnum::Value(value),
+            _ => OperandEnum::Register(self.register(token)),
+        }
+    }
+
+    /// Номер регистра для переменной, новая переменная заводится при первом упоминании
+    fn register(&mut self, name: Token) -> usize {
+        if let Some(idx) = self.registers.iter().position(|other| *other == name.text) {
+            return idx;
+        }
+        if self.registers.len() >= MAX_REGISTERS {
+            self.errors.push(CompileError::TooManyRegisters {
+                token: name.text,
+                span: name.span,
+            });
+            return 0;
+        }
+        self.registers.push(name.text);
+        self.registers.len() - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_tree() {
+        let program = parse("if wallLeft right else repeat n step endRepeat endIf").unwrap();
+        assert_eq!(program.registers, vec!["n".to_string()]);
+        let NodeEnum::If(node) = &program.main.statements[0].node else {
+            panic!("ожидался if");
+        };
+        assert_eq!(program.main.statements[0].span.column, 1);
+        assert_eq!(node.condition, ConditionEnum::Sensor(SensorEnum::WallLeft));
+        assert_eq!(
+            node.then_block.statements,
+            vec![Statement {
+                node: NodeEnum::Action(ActionEnum::TurnRight),
+                span: Span {
+                    line: 1,
+                    column: 13,
+                    start: 12,
+                    end: 17
+                },
+            }]
+        );
+        let else_block = node.else_block.as_ref().unwrap();
+        assert!(matches!(
+            else_block.statements[0].node,
+            NodeEnum::Repeat(Repeat {
+                count: OperandEnum::Register(0),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn russian_keywords_by_pragma() {
+        let english = parse("loop free and not botAhead\n  if x < 3 step inc x else call turn endIf\nendLoop\nproc turn leftOrRight endProc").unwrap();
+        let russian = parse("#lang ru\nпока свободно и не ботВпереди\n  если x < 3 шаг увеличить x иначе вызвать поворот конецЕсли\nконецПока\nпроцедура поворот налевоИлиНаправо конецПроцедуры").unwrap();
+        assert_eq!(russian.main.statements.len(), 1);
+        assert_eq!(russian.main.statements[0].span.line, 2);
+        assert_eq!(
+            crate::printer::print_program(&russian),
+            crate::printer::print_program(&english).replace("turn", "поворот")
+        );
+        //без прагмы русские слова - просто имена, а с прагмой английские
+        assert!(matches!(
+            parse("шаг").unwrap_err()[..],
+            [CompileError::UnknownCommand { .. }]
+        ));
+        assert!(parse("#lang ru\nstep").is_err());
+        let errors = parse("#lang de\nstep").unwrap_err();
+        assert_eq!((errors[0].token(), errors[0].line()), ("de", 1));
+        //после первой команды это просто комментарии
+        assert!(parse("step #syntax is odd here\nleft").is_ok());
+        assert!(parse("step\n#lang ru\nstep").is_ok());
+        assert!(parse("# ходит по кругу\n#lang ru\nшаг").is_ok());
+    }
+
+    #[test]
+    fn braces_give_same_tree() {
+        let keywords = "loop free and not botAhead\n\
+                        \x20 if wallLeft right else left endIf\n\
+                        \x20 repeat n step endRepeat\n\
+                        endLoop\n\
+                        call turn\n\
+                        proc turn if step endIf endProc";
+        let braces = "while free and not botAhead {\n\
+                      \x20 if wallLeft { right } else { left }\n\
+                      \x20 repeat n { step }\n\
+                      }\n\
+                      call turn\n\
+                      proc turn { if { step } }";
+        let expected = crate::codegen::generate(&parse(keywords).unwrap());
+        let program = parse_with_syntax(braces, SyntaxEnum::Braces).unwrap();
+        let code = crate::codegen::generate(&program);
+        assert_eq!(code.commands, expected.commands);
+        //колонки разные, а строки те же
+        let lines = |code: &crate::codegen::Code| {
+            code.source_map
+                .iter()
+                .map(|span| span.map(|span| span.line))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(lines(&code), lines(&expected));
+        //синтаксис можно задать и прагмой, она важнее того, что передали
+        let with_pragma = format!("#syntax braces\n{}", braces);
+        assert_eq!(
+            crate::codegen::generate(&parse(&with_pragma).unwrap()).commands,
+            expected.commands
+        );
+        assert_eq!(
+            SyntaxEnum::from_file_name("walker.bbot"),
+            SyntaxEnum::Braces
+        );
+
+        let errors = parse_with_syntax(
+            "if free step }\nwhile { endLoop\n}} else",
+            SyntaxEnum::Braces,
+        )
+        .unwrap_err();
+        let found: Vec<(usize, &str)> = errors.iter().map(|e| (e.line(), e.token())).collect();
+        assert_eq!(
+            found,
+            vec![(1, "if"), (2, "endLoop"), (3, "}"), (3, "else")]
+        );
+        assert!(matches!(errors[0], CompileError::ExpectedBrace { .. }));
+        assert!(parse("if free { step }").is_err());
+        //с ключевыми словами while - обычное имя
+        let program = parse("set while 2 loop while > 0 dec while endLoop").unwrap();
+        assert_eq!(program.registers, vec!["while".to_string()]);
+    }
+
+    #[test]
+    fn bad_numbers_and_names() {
+        let errors = parse("repeat 99999999999 step endRepeat").unwrap_err();
+        assert!(matches!(
+            &errors[..],
+            [CompileError::NumberTooBig { token, .. }] if token == "99999999999"
+        ));
+        assert!(matches!(
+            parse("set x -99999999999").unwrap_err()[..],
+            [CompileError::NumberTooBig { .. }]
+        ));
+        //имена - только из букв, цифр и _, остальное не переменная
+        let found = |src: &str| -> Vec<String> {
+            parse(src)
+                .unwrap_err()
+                .iter()
+                .map(|err| err.token().to_string())
+                .collect()
+        };
+        assert_eq!(found("set x 3x"), vec!["set", "3x"]);
+        assert_eq!(found("inc !"), vec!["inc", "!"]);
+        assert!(parse("set счетчик_1 -5 inc _x").is_ok());
+    }
+}
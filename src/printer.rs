@@ -0,0 +1,386 @@
+use crate::ast::{
+    ActionEnum, Block, CompareEnum, ConditionEnum, NodeEnum, OperandEnum, Proc, Program,
+    SensorEnum, Statement,
+};
+use crate::field::DirectionEnum;
+use crate::lang::LanguageEnum;
+use crate::parser::{self, SyntaxEnum};
+
+const INDENT: &str = "    ";
+
+/// Как печатать скрипт: каким набором ключевых слов и каким синтаксисом блоков
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Style {
+    pub language: LanguageEnum,
+    pub syntax: SyntaxEnum,
+}
+
+/// Канонический вид: английские слова, блоки через endIf/endLoop
+pub const CANONICAL: Style = Style {
+    language: LanguageEnum::English,
+    syntax: SyntaxEnum::Keywords,
+};
+
+/// Строка напечатанного скрипта, без отступа
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrintedLine {
+    /// Уровень вложенности
+    pub indent: usize,
+    pub text: String,
+    /// Строка исходного скрипта, из которой взялась эта
+    pub line: Option<usize>,
+    /// Дальше идет тело блока: заголовок или `else`
+    pub opens_block: bool,
+}
+
+/// Собирает из дерева скрипт в каноническом виде: одна команда на строку,
+/// вложенные блоки с отступом в 4 пробела, процедуры там же, где были в скрипте,
+/// отделенные пустыми строками. Комментарии и исходное форматирование при этом теряются
+pub fn print_program(program: &Program) -> String {
+    join_lines(&print_lines(program, CANONICAL))
+}
+
+/// То же, что `print_program`, но по строкам и в стиле `style`
+pub fn print_lines(program: &Program, style: Style) -> Vec<PrintedLine> {
+    let mut printer = Printer {
+        style,
+        registers: &program.registers,
+        lines: vec![],
+    };
+    //процедуры и основная программа идут вперемешку, в порядке исходника
+    let mut procs = program.procs.iter().peekable();
+    let mut after_proc = false;
+    for statement in program.main.statements.iter() {
+        while let Some(proc) = procs.next_if(|proc| proc.span.start < statement.span.start) {
+            printer.proc(proc);
+            after_proc = true;
+        }
+        if after_proc {
+            printer.push(0, String::new(), None);
+            after_proc = false;
+        }
+        printer.statement(statement, 0);
+    }
+    for proc in procs {
+        printer.proc(proc);
+    }
+    printer.lines
+}
+
+/// Строки с отступами в один текст, пустые строки - без отступа
+pub fn join_lines(lines: &[PrintedLine]) -> String {
+    let mut out = String::new();
+    for line in lines.iter() {
+        if !line.text.is_empty() {
+            out.push_str(&INDENT.repeat(line.indent));
+            out.push_str(&line.text);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Условие так, как его пишут в скрипте, скобки только там, где без них нельзя
+pub fn print_condition(condition: &ConditionEnum, registers: &[String]) -> String {
+    print_or(condition, registers, LanguageEnum::English)
+}
+
+struct Printer<'a> {
+    style: Style,
+    registers: &'a [String],
+    lines: Vec<PrintedLine>,
+}
+
+impl Printer<'_> {
+    /// Ключевое слово в нужном языке, `english` - как оно пишется по-английски
+    fn word<'w>(&self, english: &'w str) -> &'w str {
+        //со скобками цикл пишется через while
+        let english = match (english, self.style.syntax) {
+            ("loop", SyntaxEnum::Braces) => "while",
+            _ => english,
+        };
+        parser::translate_keyword(english, self.style.language)
+    }
+
+    fn push(&mut self, indent: usize, text: String, line: Option<usize>) {
+        self.lines.push(PrintedLine {
+            indent,
+            text,
+            line,
+            opens_block: false,
+        });
+    }
+
+    /// Заголовок блока, со скобками в конце него `{`
+    fn open(&mut self, indent: usize, header: String, line: usize) {
+        let text = match self.style.syntax {
+            SyntaxEnum::Keywords => header,
+            SyntaxEnum::Braces => format!("{} {{", header),
+        };
+        self.lines.push(PrintedLine {
+            indent,
+            text,
+            line: Some(line),
+            opens_block: true,
+        });
+    }
+
+    /// Конец блока: `end` или `}`
+    fn close(&mut self, indent: usize, end: &str, block: &Block) {
+        let text = match self.style.syntax {
+            SyntaxEnum::Keywords => self.word(end).to_string(),
+            SyntaxEnum::Braces => "}".to_string(),
+        };
+        self.push(indent, text, block.end.map(|span| span.line));
+    }
+
+    fn condition(&self, condition: &ConditionEnum) -> String {
+        print_or(condition, self.registers, self.style.language)
+    }
+
+    fn block(&mut self, block: &Block, indent: usize) {
+        for statement in block.statements.iter() {
+            self.statement(statement, indent);
+        }
+    }
+
+    /// Процедура целиком, отделенная пустой строкой от того, что напечатано до нее
+    fn proc(&mut self, proc: &Proc) {
+        if !self.lines.is_empty() {
+            self.push(0, String::new(), None);
+        }
+        let header = format!("{} {}", self.word("proc"), proc.name);
+        self.open(0, header, proc.span.line);
+        self.block(&proc.body, 1);
+        self.close(0, "endProc", &proc.body);
+    }
+
+    fn statement(&mut self, statement: &Statement, indent: usize) {
+        let registers = self.registers;
+        let line = statement.span.line;
+        let text = match &statement.node {
+            NodeEnum::Action(action) => self.word(print_action(*action)).to_string(),
+            NodeEnum::If(node) => {
+                let header = format!("{} {}", self.word("if"), self.condition(&node.condition));
+                self.open(indent, header, line);
+                self.block(&node.then_block, indent + 1);
+                match &node.else_block {
+                    Some(else_block) => {
+                        let else_line = node.then_block.end.map(|span| span.line);
+                        let text = match self.style.syntax {
+                            SyntaxEnum::Keywords => self.word("else").to_string(),
+                            SyntaxEnum::Braces => format!("}} {} {{", self.word("else")),
+                        };
+                        self.lines.push(PrintedLine {
+                            indent,
+                            text,
+                            line: else_line,
+                            opens_block: true,
+                        });
+                        self.block(else_block, indent + 1);
+                        self.close(indent, "endIf", else_block);
+                    }
+                    None => self.close(indent, "endIf", &node.then_block),
+                }
+                return;
+            }
+            NodeEnum::Loop(node) => {
+                let header = format!("{} {}", self.word("loop"), self.condition(&node.condition));
+                self.open(indent, header, line);
+                self.block(&node.body, indent + 1);
+                self.close(indent, "endLoop", &node.body);
+                return;
+            }
+            NodeEnum::Repeat(node) => {
+                let header = format!(
+                    "{} {}",
+                    self.word("repeat"),
+                    print_operand(node.count, registers)
+                );
+                self.open(indent, header, line);
+                self.block(&node.body, indent + 1);
+                self.close(indent, "endRepeat", &node.body);
+                return;
+            }
+            NodeEnum::Call(name) => format!("{} {}", self.word("call"), name),
+            NodeEnum::Stop => self.word("stop").to_string(),
+            NodeEnum::Inc(register) => {
+                format!("{} {}", self.word("inc"), registers[*register])
+            }
+            NodeEnum::Dec(register) => {
+                format!("{} {}", self.word("dec"), registers[*register])
+            }
+            NodeEnum::Set(register, value)
+            | NodeEnum::Add(register, value)
+            | NodeEnum::Sub(register, value) => {
+                let keyword = match statement.node {
+                    NodeEnum::Set(..) => "set",
+                    NodeEnum::Add(..) => "add",
+                    _ => "sub",
+                };
+                format!(
+                    "{} {} {}",
+                    self.word(keyword),
+                    registers[*register],
+                    print_operand(*value, registers)
+                )
+            }
+            NodeEnum::Say(value) => {
+                format!("{} {}", self.word("say"), print_operand(*value, registers))
+            }
+            NodeEnum::Listen(register) => {
+                format!("{} {}", self.word("listen"), registers[*register])
+            }
+        };
+        self.push(indent, text, Some(line));
+    }
+}
+
+pub fn print_action(action: ActionEnum) -> &'static str {
+    match action {
+        ActionEnum::Step => "step",
+        ActionEnum::TurnLeft => "left",
+        ActionEnum::TurnRight => "right",
+        ActionEnum::TurnRandom => "leftOrRight",
+    }
+}
+
+pub fn print_operand(operand: OperandEnum, registers: &[String]) -> String {
+    match operand {
+        OperandEnum::Value(value) => value.to_string(),
+        OperandEnum::Register(register) => registers[register].clone(),
+    }
+}
+
+fn print_or(condition: &ConditionEnum, registers: &[String], language: LanguageEnum) -> String {
+    match condition {
+        ConditionEnum::Or(left, right) => format!(
+            "{} {} {}",
+            print_or(left, registers, language),
+            parser::translate_keyword("or", language),
+            print_and(right, registers, language)
+        ),
+        condition => print_and(condition, registers, language),
+    }
+}
+
+fn print_and(condition: &ConditionEnum, registers: &[String], language: LanguageEnum) -> String {
+    match condition {
+        ConditionEnum::And(left, right) => format!(
+            "{} {} {}",
+            print_and(left, registers, language),
+            parser::translate_keyword("and", language),
+            print_not(right, registers, language)
+        ),
+        condition => print_not(condition, registers, language),
+    }
+}
+
+fn print_not(condition: &ConditionEnum, registers: &[String], language: LanguageEnum) -> String {
+    match condition {
+        ConditionEnum::Sensor(sensor) => {
+            parser::translate_keyword(print_sensor(*sensor), language).to_string()
+        }
+        ConditionEnum::Compare(left, compare, right) => format!(
+            "{} {} {}",
+            print_operand(*left, registers),
+            print_compare(*compare),
+            print_operand(*right, registers)
+        ),
+        ConditionEnum::Heard(value) => format!(
+            "{} {}",
+            parser::translate_keyword("heard", language),
+            print_operand(*value, registers)
+        ),
+        ConditionEnum::Not(inner) => format!(
+            "{} {}",
+            parser::translate_keyword("not", language),
+            print_not(inner, registers, language)
+        ),
+        condition => format!("({})", print_or(condition, registers, language)),
+    }
+}
+
+fn print_sensor(sensor: SensorEnum) -> &'static str {
+    match sensor {
+        SensorEnum::Free => "free",
+        SensorEnum::FreeBehind => "freeBehind",
+        SensorEnum::WallLeft => "wallLeft",
+        SensorEnum::WallRight => "wallRight",
+        SensorEnum::BotAhead => "botAhead",
+        SensorEnum::AtEdge => "atEdge",
+        SensorEnum::Facing(DirectionEnum::Up) => "facingNorth",
+        SensorEnum::Facing(DirectionEnum::Right) => "facingEast",
+        SensorEnum::Facing(DirectionEnum::Down) => "facingSouth",
+        SensorEnum::Facing(DirectionEnum::Left) => "facingWest",
+    }
+}
+
+fn print_compare(compare: CompareEnum) -> &'static str {
+    match compare {
+        CompareEnum::Less => "<",
+        CompareEnum::LessOrEqual => "<=",
+        CompareEnum::Greater => ">",
+        CompareEnum::GreaterOrEqual => ">=",
+        CompareEnum::Equal => "==",
+        CompareEnum::NotEqual => "!=",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codegen;
+    use crate::parser::parse;
+
+    #[test]
+    fn prints_canonical_source() {
+        let src = "proc turn leftOrRight endProc # развернуться\n\
+                   loop not botAhead and (wallLeft or x > 2)\n\
+                   if free step inc x else call turn endIf\n\
+                   repeat n sub x n dec n add x 1 endRepeat endLoop";
+        let program = parse(src).unwrap();
+        let printed = print_program(&program);
+        //процедура остается на своем месте, add x 1 не превращается в inc x
+        assert_eq!(
+            printed,
+            "proc turn\n\
+             \x20   leftOrRight\n\
+             endProc\n\
+             \n\
+             loop not botAhead and (wallLeft or x > 2)\n\
+             \x20   if free\n\
+             \x20       step\n\
+             \x20       inc x\n\
+             \x20   else\n\
+             \x20       call turn\n\
+             \x20   endIf\n\
+             \x20   repeat n\n\
+             \x20       sub x n\n\
+             \x20       dec n\n\
+             \x20       add x 1\n\
+             \x20   endRepeat\n\
+             endLoop\n"
+        );
+        let reparsed = parse(&printed).unwrap();
+        assert_eq!(print_program(&reparsed), printed);
+        assert_eq!(
+            codegen::generate(&reparsed).commands,
+            codegen::generate(&program).commands
+        );
+    }
+
+    #[test]
+    fn parens_only_where_needed() {
+        let program = parse(
+            "if (free or botAhead) or (atEdge and not (free and atEdge)) endIf \
+                             if free or (botAhead or atEdge) endIf",
+        )
+        .unwrap();
+        assert_eq!(
+            print_program(&program),
+            "if free or botAhead or atEdge and not (free and atEdge)\nendIf\n\
+             if free or (botAhead or atEdge)\nendIf\n"
+        );
+    }
+}
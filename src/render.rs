@@ -0,0 +1,130 @@
+use crate::field::{DirectionEnum, Field, FieldCellState};
+use std::fmt;
+
+/// Какими символами рисовать поле текстом
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Style {
+    pub wall: char,
+    pub empty: char,
+    /// Бот, который смотрит вверх, вправо, вниз, влево
+    pub bot_up: char,
+    pub bot_right: char,
+    pub bot_down: char,
+    pub bot_left: char,
+}
+
+/// Только ASCII, так поле печатает `Display`
+pub const ASCII: Style = Style {
+    wall: '#',
+    empty: '.',
+    bot_up: '^',
+    bot_right: '>',
+    bot_down: 'v',
+    bot_left: '<',
+};
+
+/// Стены блоками и боты стрелками, нагляднее в терминале с юникодом
+pub const UNICODE: Style = Style {
+    wall: '█',
+    empty: '·',
+    bot_up: '↑',
+    bot_right: '→',
+    bot_down: '↓',
+    bot_left: '←',
+};
+
+impl Style {
+    /// Стиль по имени: `ascii` или `unicode`
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "ascii" => Some(ASCII),
+            "unicode" => Some(UNICODE),
+            _ => None,
+        }
+    }
+
+    pub fn bot(&self, direction: DirectionEnum) -> char {
+        match direction {
+            DirectionEnum::Up => self.bot_up,
+            DirectionEnum::Right => self.bot_right,
+            DirectionEnum::Down => self.bot_down,
+            DirectionEnum::Left => self.bot_left,
+        }
+    }
+}
+
+/// Поле построчно сверху вниз, каждая строка заканчивается переводом строки.
+/// Бот рисуется поверх стены, если вдруг стоит на ней
+pub fn render(field: &Field, style: Style) -> String {
+    let mut text = String::new();
+    for y in 0..field.height {
+        for x in 0..field.width {
+            let cell = match field.get_cell_state(x, y) {
+                Some(FieldCellState::Bot(_)) => field
+                    .get_bot_at(x, y)
+                    .and_then(|idx| field.get_bot_position(idx))
+                    .map_or(style.empty, |(_, _, direction)| style.bot(direction)),
+                Some(FieldCellState::Wall) => style.wall,
+                None => style.empty,
+            };
+            text.push(cell);
+        }
+        text.push('\n');
+    }
+    text
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", render(self, ASCII))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field() -> Field {
+        let mut fld = Field::with_seed(42);
+        fld.width = 6;
+        fld.height = 3;
+        fld.turn_wall(0, 0);
+        fld.turn_wall(5, 2);
+        fld.add_bot("step".to_string()).unwrap();
+        fld.add_bot("left".to_string()).unwrap();
+        fld
+    }
+
+    #[test]
+    fn snapshots() {
+        let mut fld = field();
+        assert_eq!(fld.to_string(), SNAPSHOT_START);
+        fld.step().unwrap();
+        assert_eq!(fld.to_string(), SNAPSHOT_STEP);
+        assert_eq!(
+            render(&fld, UNICODE),
+            SNAPSHOT_STEP
+                .replace('#', "█")
+                .replace('.', "·")
+                .replace('^', "↑")
+                .replace('>', "→")
+                .replace('v', "↓")
+                .replace('<', "←")
+        );
+        assert_eq!(Style::from_code("unicode"), Some(UNICODE));
+        assert_eq!(Style::from_code("emoji"), None);
+    }
+
+    const SNAPSHOT_START: &str = "\
+#.vv..
+......
+.....#
+";
+
+    //первый шагнул вниз, второй повернулся налево - для того, кто смотрит вниз, это вправо
+    const SNAPSHOT_STEP: &str = "\
+#.>...
+...v..
+.....#
+";
+}